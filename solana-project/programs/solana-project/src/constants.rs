@@ -1,4 +1,159 @@
-pub const CORE_BRIDGE_ADDRESS: &str = "3u8hJUVTA4jH1wYAyUur7FFZVQ8H635K3tSHHF4ssjQ5";
-pub const TOKEN_BRIDGE_ADDRESS: &str = "DZnkkTmCiFWfYTfT41X3Rd1kDgozqzxWaHqsw6W4x2oe";
+use crate::errors::MessengerError;
+use anchor_lang::prelude::Pubkey;
 
-pub const EVM_CHAIN_ADDRESS_LENGTH: usize = 42;
\ No newline at end of file
+// dSuyjPvmWdBr68FRG9Q433Py6YxeiTMZni7WiF74GQE, as bytes so it isn't parsed at runtime.
+pub const ZEBEC_PROGRAM_ID: Pubkey = Pubkey::new_from_array([
+    9, 86, 53, 163, 16, 169, 19, 2, 113, 208, 124, 18, 105, 5, 46, 97, 175, 115, 102, 38, 11, 37,
+    129, 144, 10, 32, 27, 146, 7, 67, 138, 183,
+]);
+
+// TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb, whose mints may charge a transfer fee.
+pub const TOKEN_2022_PROGRAM_ID: Pubkey = Pubkey::new_from_array([
+    6, 221, 246, 225, 238, 117, 143, 222, 24, 66, 93, 188, 228, 108, 205, 218, 182, 26, 252, 77,
+    131, 185, 13, 39, 254, 189, 249, 40, 216, 161, 139, 252,
+]);
+// Token-2022 extensions are TLV entries after the 165 bytes of a base account and
+// the account type byte.
+pub const TOKEN_2022_EXTENSIONS_OFFSET: usize = 166;
+pub const TRANSFER_FEE_CONFIG_EXTENSION: u16 = 1;
+// Two authorities, the withheld amount, then the older and newer TransferFee.
+pub const TRANSFER_FEE_CONFIG_LEN: usize = 32 + 32 + 8 + 18 + 18;
+pub const MAX_FEE_BASIS_POINTS: u128 = 10_000;
+
+pub const EVM_CHAIN_ADDRESS_LENGTH: usize = 42;
+// Hex digits of a Wormhole-normalized 32 byte emitter address, the form stored.
+pub const WORMHOLE_ADDRESS_LENGTH: usize = 64;
+// Largest fixed layout payload: update_stream, 185 bytes plus the optional deadline.
+pub const MAX_PAYLOAD_LEN: usize = 193;
+// Relay payloads carry a whole instruction.
+pub const MAX_RELAY_PAYLOAD_LEN: usize = 1024;
+// First byte of payloads with an explicit wire format version, never a valid opcode.
+pub const PAYLOAD_VERSION_PREFIX: u8 = 0xff;
+// Bytes of the sender wallet in every payload layout.
+pub const SENDER_LEN: usize = 32;
+
+// Bumped whenever the layout of an emitted event changes.
+pub const EVENT_SCHEMA_VERSION: u8 = 2;
+
+pub const MAX_REINDEX_PAGE_SIZE: u32 = 16;
+
+// Keeps a StateExport event well inside the log limit.
+pub const MAX_EXPORT_PAGE_SIZE: usize = 16;
+
+// Keeps a full store_msg_batch within the compute budget.
+pub const MAX_STORE_MSG_BATCH_SIZE: u8 = 4;
+// Emitter accounts created by one register_chains call.
+pub const MAX_REGISTER_CHAINS_BATCH_SIZE: usize = 4;
+// Capacity of Config.allowed_mints.
+pub const MAX_ALLOWED_MINTS: usize = 16;
+// Capacity of Config.target_chains.
+pub const MAX_TARGET_CHAINS: usize = 16;
+// Capacity of Config.registered_chains.
+pub const MAX_REGISTERED_CHAINS: usize = 16;
+// Wormhole chain id of Solana, never a valid target for an outbound transfer.
+pub const CHAIN_ID_SOLANA: u16 = 1;
+// Decimals the token bridge carries, native amounts of mints with more are truncated.
+pub const BRIDGE_MAX_DECIMALS: u8 = 8;
+// core_bridge_vaa, processed_vaa, data_storage, txn_count, txn_status
+pub const STORE_MSG_BATCH_ACCOUNTS: usize = 5;
+
+// Rough compute cost of one batched message, checked against config.batch_compute_budget:
+// a base for VAA verification and parsing, plus each account created and payload byte.
+pub const STORE_MSG_BASE_CU: u64 = 40_000;
+pub const STORE_MSG_CREATE_ACCOUNT_CU: u64 = 10_000;
+pub const STORE_MSG_PAYLOAD_BYTE_CU: u64 = 50;
+
+// Bumped whenever a field is appended to TransactionData.
+pub const DATA_STORAGE_VERSION: u8 = 6;
+
+// Account sizes, shared by StoreMsg and store_msg_batch.
+pub const CONFIG_SPACE: usize = 8
+    + 32
+    + 4
+    + 32
+    + 32
+    + 8
+    + 8
+    + 1
+    + 1
+    + 1
+    + 1
+    + 1
+    + 1
+    + 1
+    + 1
+    + 1
+    + 1
+    + 1
+    + 8
+    + 8
+    + 8
+    + 4
+    + 32 * MAX_ALLOWED_MINTS
+    + 4
+    + 2 * MAX_TARGET_CHAINS
+    + 4
+    + 2 * MAX_REGISTERED_CHAINS
+    + 32
+    + 32
+    + 32
+    + 8
+    + 8;
+pub const PROCESSED_VAA_SPACE: usize = 8;
+pub const DATA_STORAGE_SPACE: usize = 8 + 285;
+pub const TXN_COUNT_SPACE: usize = 8 + 8;
+pub const TXN_STATUS_SPACE: usize = 8 + 1 + 1 + 1;
+pub const RATE_LIMIT_SPACE: usize = 8 + 8 + 8;
+pub const MAX_DAILY_STATS_MINTS: usize = 16;
+pub const DAILY_STATS_SPACE: usize = 8 + 8 + 8 + 4 + (32 + 8 + 8) * MAX_DAILY_STATS_MINTS;
+pub const EMITTER_ACC_SPACE: usize = 8 + 2 + 4 + WORMHOLE_ADDRESS_LENGTH + 1 + 32 + 8;
+
+pub const SECONDS_PER_DAY: i64 = 86_400;
+
+// Opcodes carried in payload[0], mirroring the constants in the EVM Encoder.
+#[repr(u8)]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum MessageCode {
+    Stream = 2,
+    WithdrawStream = 4,
+    Deposit = 6,
+    Pause = 8,
+    Withdraw = 10,
+    InstantTransfer = 12,
+    UpdateStream = 14,
+    CancelStream = 16,
+    DirectTransfer = 17,
+    StreamWithCliff = 20,
+    Relay = 22,
+}
+
+impl TryFrom<u64> for MessageCode {
+    type Error = MessengerError;
+
+    fn try_from(code: u64) -> std::result::Result<Self, Self::Error> {
+        match code {
+            2 => Ok(MessageCode::Stream),
+            4 => Ok(MessageCode::WithdrawStream),
+            6 => Ok(MessageCode::Deposit),
+            8 => Ok(MessageCode::Pause),
+            10 => Ok(MessageCode::Withdraw),
+            12 => Ok(MessageCode::InstantTransfer),
+            14 => Ok(MessageCode::UpdateStream),
+            16 => Ok(MessageCode::CancelStream),
+            17 => Ok(MessageCode::DirectTransfer),
+            20 => Ok(MessageCode::StreamWithCliff),
+            22 => Ok(MessageCode::Relay),
+            _ => Err(MessengerError::UnknownOpcode),
+        }
+    }
+}
+
+impl MessageCode {
+    // Direct transfers go out through the token bridge, everything else is a Zebec instruction.
+    pub fn program_id(&self, token_bridge: Pubkey) -> Pubkey {
+        match self {
+            MessageCode::DirectTransfer => token_bridge,
+            _ => ZEBEC_PROGRAM_ID,
+        }
+    }
+}
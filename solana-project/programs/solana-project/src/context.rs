@@ -1,21 +1,23 @@
 use anchor_lang::prelude::*;
 use anchor_spl::token::Token;
 use anchor_spl::token::TokenAccount;
+use anchor_spl::token::Mint;
 use crate::constants::*;
-use crate::portal::TokenPortalBridge;
+use crate::errors::MessengerError;
 use crate::state::*;
-use std::str::FromStr;
 use crate::wormhole::*;
-use hex::decode;
+use crate::{chain_id_seed, current_day};
 
+// init, not init_if_needed: a second call would hand the owner and the bridges
+// trusted for VAAs to whoever sends it.
 #[derive(Accounts)]
 pub struct Initialize<'info> {
     #[account(
-        init_if_needed,
+        init,
         seeds=[b"config".as_ref()],
         payer=owner,
         bump,
-        space=8+32+4
+        space=CONFIG_SPACE
     )]
     pub config: Account<'info, Config>,
     #[account(mut)]
@@ -23,14 +25,306 @@ pub struct Initialize<'info> {
     pub system_program: Program<'info, System>
 }
 
+#[derive(Accounts)]
+pub struct UpdateConfig<'info> {
+    pub owner: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump,
+        constraint = config.owner == owner.key() @ MessengerError::InvalidCaller
+    )]
+    pub config: Account<'info, Config>,
+}
+
+// Recovery of tokens left in an account a foreign wallet's PDA owns, e.g. a wSOL
+// account a failed transfer didn't close. The owner receives them.
+#[derive(Accounts)]
+#[instruction(sender: [u8; 32], from_chain_id: u64)]
+pub struct SweepTokens<'info> {
+    pub owner: Signer<'info>,
+    #[account(
+        seeds = [b"config"],
+        bump,
+        constraint = config.owner == owner.key() @ MessengerError::InvalidCaller
+    )]
+    pub config: Account<'info, Config>,
+
+    ///CHECK: pda seeds checked
+    #[account(
+        seeds = [
+            &sender,
+            chain_id_seed(from_chain_id).as_bytes()
+        ],
+        bump
+    )]
+    pub pda_signer: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        constraint = source.owner == pda_signer.key() @ MessengerError::InvalidSweepAccount
+    )]
+    pub source: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint = destination.owner == owner.key() @ MessengerError::InvalidSweepAccount,
+        constraint = destination.mint == source.mint @ MessengerError::InvalidSweepAccount
+    )]
+    pub destination: Box<Account<'info, TokenAccount>>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct GetStatus<'info> {
+    #[account(seeds = [b"config"], bump)]
+    pub config: Account<'info, Config>,
+
+    #[account(
+        seeds = [b"Bridge"],
+        seeds::program = config.core_bridge,
+        bump
+    )]
+    /// CHECK: bridge config
+    pub bridge_config: AccountInfo<'info>,
+}
+
+// Read only. The sequence account of the token bridge emitter, the one
+// every outbound transfer posts its message under.
+#[derive(Accounts)]
+pub struct GetNextSequence<'info> {
+    #[account(seeds = [b"config"], bump)]
+    pub config: Account<'info, Config>,
+
+    #[account(
+        seeds = [b"emitter"],
+        seeds::program = config.token_bridge,
+        bump
+    )]
+    /// CHECK: portal emitter
+    pub portal_emitter: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"Sequence", portal_emitter.key().as_ref()],
+        seeds::program = config.core_bridge,
+        bump
+    )]
+    /// CHECK: portal sequence
+    pub portal_sequence: AccountInfo<'info>,
+}
+
+// Read only.
+#[derive(Accounts)]
+pub struct GetTransaction<'info> {
+    pub transaction: Account<'info, Transaction>,
+}
+
+// Read only, decode_only never writes.
+#[derive(Accounts)]
+pub struct DecodeOnly<'info> {
+    #[account(seeds = [b"config"], bump)]
+    pub config: Account<'info, Config>,
+    // Source of the source_decimals amounts are rescaled from.
+    pub emitter_acc: Account<'info, EmitterAddrAccount>,
+}
+
+#[derive(Accounts)]
+pub struct PostedVaaLookup<'info> {
+    #[account(seeds = [b"config"], bump)]
+    pub config: Account<'info, Config>,
+}
+
+#[derive(Accounts)]
+pub struct ExportState<'info> {
+    pub owner: Signer<'info>,
+    #[account(
+        seeds = [b"config"],
+        bump,
+        constraint = config.owner == owner.key() @ MessengerError::InvalidCaller
+    )]
+    pub config: Account<'info, Config>,
+}
+
+#[derive(Accounts)]
+pub struct Reindex<'info> {
+    pub owner: Signer<'info>,
+    #[account(
+        seeds = [b"config"],
+        bump,
+        constraint = config.owner == owner.key() @ MessengerError::InvalidCaller
+    )]
+    pub config: Account<'info, Config>,
+}
+
+// Config can't be deserialized before it is grown, the owner is checked in the handler.
+#[derive(Accounts)]
+pub struct MigrateConfig<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    pub system_program: Program<'info, System>,
+    /// CHECK: older layouts don't deserialize as Config, the discriminator is checked in the handler
+    #[account(mut, seeds = [b"config"], bump, owner = crate::ID)]
+    pub config: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(sender: [u8; 32])]
+pub struct MigrateTxnCount<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    pub system_program: Program<'info, System>,
+    #[account(
+        seeds = [b"config"],
+        bump,
+        constraint = config.owner == owner.key() @ MessengerError::InvalidCaller
+    )]
+    pub config: Account<'info, Config>,
+    /// CHECK: the u8 layout doesn't deserialize as Count, the discriminator is checked in the handler
+    #[account(
+        mut,
+        seeds = [b"txn_count".as_ref(), &sender],
+        bump,
+        owner = crate::ID
+    )]
+    pub txn_count: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(sender: [u8; 32], count: u8)]
+pub struct MigrateLegacyOperation<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    pub system_program: Program<'info, System>,
+    #[account(
+        seeds = [b"config"],
+        bump,
+        constraint = config.owner == owner.key() @ MessengerError::InvalidCaller
+    )]
+    pub config: Account<'info, Config>,
+    /// CHECK: copied byte for byte, seeds and owner checked here
+    #[account(
+        mut,
+        seeds = [b"data_store".as_ref(), &sender, &[count]],
+        bump,
+        owner = crate::ID
+    )]
+    pub legacy_data_storage: UncheckedAccount<'info>,
+    /// CHECK: copied byte for byte, seeds and owner checked here
+    #[account(
+        mut,
+        seeds = [b"txn_status".as_ref(), &sender, &[count]],
+        bump,
+        owner = crate::ID
+    )]
+    pub legacy_txn_status: UncheckedAccount<'info>,
+    /// CHECK: created in the handler, which checks the seeds
+    #[account(mut)]
+    pub data_storage: UncheckedAccount<'info>,
+    /// CHECK: created in the handler, which checks the seeds
+    #[account(mut)]
+    pub txn_status: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateDataStorage<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    pub system_program: Program<'info, System>,
+    #[account(
+        seeds = [b"config"],
+        bump,
+        constraint = config.owner == owner.key() @ MessengerError::InvalidCaller
+    )]
+    pub config: Account<'info, Config>,
+    /// CHECK: older layouts don't deserialize as TransactionData, the discriminator is checked in the handler
+    #[account(mut, owner = crate::ID)]
+    pub data_storage: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CancelBuiltTransaction<'info> {
+    pub owner: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump,
+        constraint = config.owner == owner.key() @ MessengerError::InvalidCaller
+    )]
+    pub config: Account<'info, Config>,
+    #[account(
+        mut,
+        close = payer,
+        constraint = !transaction.did_execute @ MessengerError::AlreadyExecuted
+    )]
+    pub transaction: Box<Account<'info, Transaction>>,
+    /// CHECK: only receives the rent of transaction
+    #[account(mut, address = transaction.payer)]
+    pub payer: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(sender: [u8; 32], value: u64)]
+pub struct ResetTxnCount<'info> {
+    pub owner: Signer<'info>,
+    #[account(
+        seeds = [b"config"],
+        bump,
+        constraint = config.owner == owner.key() @ MessengerError::InvalidCaller
+    )]
+    pub config: Account<'info, Config>,
+    #[account(
+        mut,
+        seeds = [
+            b"txn_count".as_ref(),
+            &sender,
+        ],
+        bump
+    )]
+    pub txn_count: Account<'info, Count>,
+
+    // The operation the next store_msg would create after the reset.
+    #[account(
+        seeds = [
+            b"data_store".as_ref(),
+            &sender,
+            value.saturating_add(1).to_be_bytes().as_ref()
+        ],
+        bump
+    )]
+    /// CHECK: only checked to be empty
+    pub next_data_storage: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ResetRateLimit<'info> {
+    pub owner: Signer<'info>,
+    #[account(
+        seeds = [b"config"],
+        bump,
+        constraint = config.owner == owner.key() @ MessengerError::InvalidCaller
+    )]
+    pub config: Account<'info, Config>,
+    #[account(
+        mut,
+        seeds = [b"rate_limit".as_ref()],
+        bump
+    )]
+    pub rate_limit: Account<'info, RateLimit>,
+}
+
 #[derive(Accounts)]
 #[instruction(chain_id:u16, emitter_addr:String)]
 pub struct RegisterChain<'info> {
     #[account(mut)]
     pub owner: Signer<'info>,
     pub system_program: Program<'info, System>,
+    // Registration decides which emitters are trusted, so only the owner may do it.
     #[account(
-        constraint = config.owner == owner.key()
+        mut,
+        seeds = [b"config"],
+        bump,
+        constraint = config.owner == owner.key() @ MessengerError::InvalidCaller
     )]
     pub config: Account<'info, Config>,
     #[account(
@@ -38,7 +332,62 @@ pub struct RegisterChain<'info> {
         seeds=[b"EmitterAddress".as_ref(), chain_id.to_be_bytes().as_ref()],
         payer=owner,
         bump,
-        space=8 + 2 + 4 + EVM_CHAIN_ADDRESS_LENGTH
+        space=EMITTER_ACC_SPACE
+    )]
+    pub emitter_acc: Account<'info, EmitterAddrAccount>,
+}
+
+// The emitter accounts of register_chains are passed in remaining_accounts, in the
+// order of the entries.
+#[derive(Accounts)]
+pub struct RegisterChains<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    pub system_program: Program<'info, System>,
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump,
+        constraint = config.owner == owner.key() @ MessengerError::InvalidCaller
+    )]
+    pub config: Account<'info, Config>,
+}
+
+#[derive(Accounts)]
+#[instruction(chain_id:u16)]
+pub struct DeregisterChain<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump,
+        constraint = config.owner == owner.key() @ MessengerError::InvalidCaller
+    )]
+    pub config: Account<'info, Config>,
+    #[account(
+        mut,
+        close = owner,
+        seeds=[b"EmitterAddress".as_ref(), chain_id.to_be_bytes().as_ref()],
+        bump
+    )]
+    pub emitter_acc: Account<'info, EmitterAddrAccount>,
+}
+
+#[derive(Accounts)]
+#[instruction(chain_id:u16)]
+pub struct UpdateChain<'info> {
+    pub owner: Signer<'info>,
+    #[account(
+        seeds = [b"config"],
+        bump,
+        constraint = config.owner == owner.key() @ MessengerError::InvalidCaller
+    )]
+    pub config: Account<'info, Config>,
+    #[account(
+        mut,
+        seeds=[b"EmitterAddress".as_ref(), chain_id.to_be_bytes().as_ref()],
+        bump
     )]
     pub emitter_acc: Account<'info, EmitterAddrAccount>,
 }
@@ -58,12 +407,19 @@ pub struct CreateTransaction<'info> {
     pub zebec_eoa: Signer<'info>,
     pub system_program: Program<'info, System>,
 
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump,
+    )]
+    pub config: Account<'info, Config>,
+
    #[account(
         mut,
         seeds = [
             b"data_store".as_ref(),
             &sender, 
-            &[txn_count.count]
+            txn_count.count.to_be_bytes().as_ref()
         ],
         bump
     )]
@@ -87,7 +443,7 @@ pub struct CreateTransaction<'info> {
         seeds = [
             b"txn_status".as_ref(),
             &sender,
-            &[txn_count.count]
+            txn_count.count.to_be_bytes().as_ref()
         ],
         bump
     )]
@@ -110,12 +466,18 @@ pub struct CETransaction<'info> {
     pub zebec_eoa: Signer<'info>,
     pub system_program: Program<'info, System>,
 
+    #[account(
+        seeds = [b"config"],
+        bump,
+    )]
+    pub config: Account<'info, Config>,
+
    #[account(
         mut,
         seeds = [
             b"data_store".as_ref(),
             &sender, 
-            &[txn_count.count]
+            txn_count.count.to_be_bytes().as_ref()
         ],
         bump
     )]
@@ -136,7 +498,7 @@ pub struct CETransaction<'info> {
         mut,
         seeds = [
             &sender,
-            &chain_id
+            chain_id_seed(data_storage.from_chain_id).as_bytes()
         ],
         bump
     )]
@@ -147,7 +509,7 @@ pub struct CETransaction<'info> {
         seeds = [
             b"txn_status".as_ref(),
             &sender,
-            &[txn_count.count]
+            txn_count.count.to_be_bytes().as_ref()
         ],
         bump
     )]
@@ -169,7 +531,7 @@ pub struct DirectTransferNative<'info> {
         seeds = [
             b"data_store".as_ref(),
             &sender, 
-            &[txn_count.count]
+            txn_count.count.to_be_bytes().as_ref()
         ],
         bump
     )]
@@ -191,7 +553,7 @@ pub struct DirectTransferNative<'info> {
         seeds = [
             b"txn_status".as_ref(),
             &sender,
-            &[txn_count.count]
+            txn_count.count.to_be_bytes().as_ref()
         ],
         bump
     )]
@@ -202,7 +564,7 @@ pub struct DirectTransferNative<'info> {
         mut,
         seeds = [
             &sender,
-            &chain_id
+            chain_id_seed(data_storage.from_chain_id).as_bytes()
         ],
         bump
     )]
@@ -274,7 +636,11 @@ pub struct DirectTransferNative<'info> {
     
     #[account(
         mut,
-        signer
+        signer,
+        // The bridge initializes the message, so it has to be a fresh keypair.
+        constraint = portal_message.data_is_empty()
+            && portal_message.owner == &anchor_lang::solana_program::system_program::ID
+            @ MessengerError::PortalMessageReused
     )]
     /// CHECK: portal message
     pub portal_message: AccountInfo<'info>,
@@ -306,26 +672,227 @@ pub struct DirectTransferNative<'info> {
     /// CHECK: bridge fee collector
     pub bridge_fee_collector: AccountInfo<'info>,
 
-    pub clock: Sysvar<'info, Clock>,
+    /// CHECK: passed through to the bridge, checked to be the clock sysvar
+    pub clock: UncheckedAccount<'info>,
 
-    pub rent: Sysvar<'info, Rent>,
+    /// CHECK: passed through to the bridge, checked to be the rent sysvar
+    pub rent: UncheckedAccount<'info>,
 
     pub system_program: Program<'info, System>,
 
-    pub portal_bridge_program: Program<'info, TokenPortalBridge>,
+    /// CHECK: checked against the token bridge in config
+    #[account(executable, address = config.token_bridge)]
+    pub portal_bridge_program: UncheckedAccount<'info>,
 
-    pub core_bridge_program: Program<'info, WormholeCoreBridge>,
+    /// CHECK: checked against the core bridge in config
+    #[account(executable, address = config.core_bridge)]
+    pub core_bridge_program: UncheckedAccount<'info>,
 
-    pub token_program: Program<'info, Token>
+    pub token_program: Program<'info, Token>,
 
+    #[account(
+        init_if_needed,
+        payer = zebec_eoa,
+        space = RATE_LIMIT_SPACE,
+        seeds = [b"rate_limit".as_ref()],
+        bump
+    )]
+    pub rate_limit: Box<Account<'info, RateLimit>>,
+}
+
+#[derive(Accounts)]
+#[instruction( 
+    sender: [u8; 32],
+    chain_id: Vec<u8>,
+)]
+pub struct DirectTransferSol<'info> {
+    // One of the owners. Checked in the handler.
+    #[account(mut)]
+    pub zebec_eoa: Signer<'info>,
+
+   #[account(
+        mut,
+        seeds = [
+            b"data_store".as_ref(),
+            &sender, 
+            txn_count.count.to_be_bytes().as_ref()
+        ],
+        bump
+    )]
+    pub data_storage: Box<Account<'info, TransactionData>>,
+
+    #[account(
+        mut,
+        constraint = data_storage.sender == sender,
+        seeds = [
+            b"txn_count".as_ref(),
+            &sender,
+        ],
+        bump
+    )]
+    pub txn_count: Box<Account<'info, Count>>,
+
+    #[account(
+        mut, 
+        seeds = [
+            b"txn_status".as_ref(),
+            &sender,
+            txn_count.count.to_be_bytes().as_ref()
+        ],
+        bump
+    )]
+    pub txn_status: Account<'info, TransactionStatus>,
+
+    ///CHECK: pda seeds checked
+    #[account(
+        mut,
+        seeds = [
+            &sender,
+            chain_id_seed(data_storage.from_chain_id).as_bytes()
+        ],
+        bump
+    )]
+    pub pda_signer: UncheckedAccount<'info>,
+
+    //Native Transfer
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump,
+    )]
+    pub config: Account<'info, Config>,
+
+    //from_owner = pda_signer
+
+    #[account(
+        mut,
+        seeds = [b"config"],
+        seeds::program = portal_bridge_program.key(),
+        bump,
+    )]
+    /// CHECK: portal config
+    pub portal_config: AccountInfo<'info>,
+    
+    #[account(mut, address = anchor_spl::token::spl_token::native_mint::ID)]
+    pub mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        mut,
+        seeds = [mint.key().as_ref()],
+        seeds::program = portal_bridge_program.key(),
+        bump
+    )]
+    /// CHECK: portal custody
+    pub portal_custody: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"authority_signer"],
+        seeds::program = portal_bridge_program.key(),
+        bump
+    )]
+    /// CHECK: portal authority signer
+    pub portal_authority_signer: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"custody_signer"],
+        seeds::program = portal_bridge_program.key(),
+        bump
+    )]
+    /// CHECK: portal custody signer
+    pub portal_custody_signer: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"Bridge"],
+        seeds::program = core_bridge_program.key(),
+        bump
+    )]
+    /// CHECK: bridge config
+    pub bridge_config: AccountInfo<'info>,
+    
+    #[account(
+        mut,
+        signer,
+        // The bridge initializes the message, so it has to be a fresh keypair.
+        constraint = portal_message.data_is_empty()
+            && portal_message.owner == &anchor_lang::solana_program::system_program::ID
+            @ MessengerError::PortalMessageReused
+    )]
+    /// CHECK: portal message
+    pub portal_message: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"emitter"],
+        seeds::program = portal_bridge_program.key(),
+        bump
+    )]
+    /// CHECK: portal emitter
+    pub portal_emitter: AccountInfo<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"Sequence", portal_emitter.key().as_ref()],
+        seeds::program = core_bridge_program.key(),
+        bump
+    )]
+    /// CHECK: portal sequence
+    pub portal_sequence: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"fee_collector"],
+        seeds::program = core_bridge_program.key(),
+        bump
+    )]
+    /// CHECK: bridge fee collector
+    pub bridge_fee_collector: AccountInfo<'info>,
+
+    /// CHECK: passed through to the bridge, checked to be the clock sysvar
+    pub clock: UncheckedAccount<'info>,
+
+    /// CHECK: passed through to the bridge, checked to be the rent sysvar
+    pub rent: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+
+    /// CHECK: checked against the token bridge in config
+    #[account(executable, address = config.token_bridge)]
+    pub portal_bridge_program: UncheckedAccount<'info>,
+
+    /// CHECK: checked against the core bridge in config
+    #[account(executable, address = config.core_bridge)]
+    pub core_bridge_program: UncheckedAccount<'info>,
+
+    pub token_program: Program<'info, Token>,
+
+    // Temporary wSOL account the sender's lamports are wrapped into. Only created by
+    // transfer_sol and closed again once the bridge has taken the tokens, so staging
+    // leaves it alone. Rent is fronted by zebec_eoa.
+    #[account(
+        mut,
+        seeds = [b"wsol", pda_signer.key().as_ref()],
+        bump,
+    )]
+    /// CHECK: created as a wSOL account of pda_signer in transfer_sol
+    pub from: UncheckedAccount<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = zebec_eoa,
+        space = RATE_LIMIT_SPACE,
+        seeds = [b"rate_limit".as_ref()],
+        bump
+    )]
+    pub rate_limit: Box<Account<'info, RateLimit>>,
 }
 
 #[derive(Accounts)]
 #[instruction( 
     sender: [u8; 32],
     sender_chain: Vec<u8>,
-    _token_address: Vec<u8>,
-    _token_chain: u16,
+    token_address: Vec<u8>,
+    token_chain: u16,
 )]
 pub struct DirectTransferWrapped<'info> {
     // One of the owners. Checked in the handler.
@@ -337,7 +904,7 @@ pub struct DirectTransferWrapped<'info> {
         seeds = [
             b"data_store".as_ref(),
             &sender, 
-            &[txn_count.count]
+            txn_count.count.to_be_bytes().as_ref()
         ],
         bump
     )]
@@ -361,7 +928,7 @@ pub struct DirectTransferWrapped<'info> {
         seeds = [
             b"txn_status".as_ref(),
             &sender,
-            &[txn_count.count]
+            txn_count.count.to_be_bytes().as_ref()
         ],
         bump
     )]
@@ -372,7 +939,7 @@ pub struct DirectTransferWrapped<'info> {
         mut,
         seeds = [
             &sender,
-            &sender_chain
+            chain_id_seed(data_storage.from_chain_id).as_bytes()
         ],
         bump
     )]
@@ -406,8 +973,8 @@ pub struct DirectTransferWrapped<'info> {
         mut,
         seeds = [
             b"wrapped",
-            _token_chain.to_be_bytes().as_ref(),
-            _token_address.as_ref()
+            token_chain.to_be_bytes().as_ref(),
+            token_address.as_ref()
         ],
         seeds::program = portal_bridge_program.key(),
         bump,
@@ -446,7 +1013,11 @@ pub struct DirectTransferWrapped<'info> {
 
     #[account(
         mut,
-        signer
+        signer,
+        // The bridge initializes the message, so it has to be a fresh keypair.
+        constraint = portal_message.data_is_empty()
+            && portal_message.owner == &anchor_lang::solana_program::system_program::ID
+            @ MessengerError::PortalMessageReused
     )]
     /// CHECK: portal message
     pub portal_message: AccountInfo<'info>,
@@ -478,18 +1049,32 @@ pub struct DirectTransferWrapped<'info> {
     /// CHECK: bridge fee collector
     pub bridge_fee_collector: AccountInfo<'info>,
 
-    pub clock: Sysvar<'info, Clock>,
+    /// CHECK: passed through to the bridge, checked to be the clock sysvar
+    pub clock: UncheckedAccount<'info>,
 
-    pub rent: Sysvar<'info, Rent>,
+    /// CHECK: passed through to the bridge, checked to be the rent sysvar
+    pub rent: UncheckedAccount<'info>,
 
     pub system_program: Program<'info, System>,
 
-    pub portal_bridge_program: Program<'info, TokenPortalBridge>,
+    /// CHECK: checked against the token bridge in config
+    #[account(executable, address = config.token_bridge)]
+    pub portal_bridge_program: UncheckedAccount<'info>,
 
-    pub core_bridge_program: Program<'info, WormholeCoreBridge>,
+    /// CHECK: checked against the core bridge in config
+    #[account(executable, address = config.core_bridge)]
+    pub core_bridge_program: UncheckedAccount<'info>,
 
-    pub token_program: Program<'info, Token>
+    pub token_program: Program<'info, Token>,
 
+    #[account(
+        init_if_needed,
+        payer = zebec_eoa,
+        space = RATE_LIMIT_SPACE,
+        seeds = [b"rate_limit".as_ref()],
+        bump
+    )]
+    pub rate_limit: Box<Account<'info, RateLimit>>,
 }
 
 
@@ -498,7 +1083,7 @@ pub struct DirectTransferWrapped<'info> {
     pid: Pubkey,
     accs: Vec<TransactionAccount>,
     data: Vec<u8>,
-    sender: [u8; 32],
+    withdrawer: [u8; 32],
 )]
 pub struct CreateTransactionReceiver<'info> {
     #[account(zero, signer)]
@@ -508,12 +1093,19 @@ pub struct CreateTransactionReceiver<'info> {
     pub zebec_eoa: Signer<'info>,
     pub system_program: Program<'info, System>,
 
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump,
+    )]
+    pub config: Account<'info, Config>,
+
    #[account(
         mut,
         seeds = [
             b"data_store".as_ref(),
-            &sender, 
-            &[txn_count.count]
+            &withdrawer, 
+            txn_count.count.to_be_bytes().as_ref()
         ],
         bump
     )]
@@ -522,10 +1114,10 @@ pub struct CreateTransactionReceiver<'info> {
 
     #[account(
         mut,
-        constraint = data_storage.receiver == sender,
+        constraint = data_storage.receiver == withdrawer @ MessengerError::WithdrawerMismatch,
         seeds = [
             b"txn_count".as_ref(),
-            &sender,
+            &withdrawer,
         ],
         bump
     )]
@@ -535,8 +1127,8 @@ pub struct CreateTransactionReceiver<'info> {
         mut, 
         seeds = [
             b"txn_status".as_ref(),
-            &sender,
-            &[txn_count.count]
+            &withdrawer,
+            txn_count.count.to_be_bytes().as_ref()
         ],
         bump
     )]
@@ -545,7 +1137,7 @@ pub struct CreateTransactionReceiver<'info> {
 
 #[derive(Accounts)]
 #[instruction(
-    current_count: u8, 
+    current_count: u64, 
     sender: [u8; 32], 
 )]
 pub struct StoreMsg<'info>{
@@ -555,34 +1147,43 @@ pub struct StoreMsg<'info>{
     pub payer: Signer<'info>,
     pub system_program: Program<'info, System>,
 
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump,
+    )]
+    pub config: Account<'info, Config>,
+
     #[account(
         init,
         seeds=[
-            &decode(&emitter_acc.emitter_addr.as_str()).unwrap()[..],
+            &emitter_acc.emitter_bytes[..],
             emitter_acc.chain_id.to_be_bytes().as_ref(),
             (PostedMessageData::try_from_slice(&core_bridge_vaa.data.borrow())?.0).sequence.to_be_bytes().as_ref()
         ],
         payer=payer,
         bump,
-        space=8
+        space=PROCESSED_VAA_SPACE
     )]
     pub processed_vaa: Account<'info, ProcessedVAA>,
     pub emitter_acc: Account<'info, EmitterAddrAccount>,
     /// This requires some fancy hashing, so confirm it's derived address in the function itself.
     #[account(
-        constraint = core_bridge_vaa.to_account_info().owner == &Pubkey::from_str(CORE_BRIDGE_ADDRESS).unwrap()
+        constraint = core_bridge_vaa.to_account_info().owner == &config.core_bridge
     )]
     /// CHECK: This account is owned by Core Bridge so we trust it
     pub core_bridge_vaa: AccountInfo<'info>,
 
+    // One account per message: keyed by the sender's running txn_count, so pending
+    // operations of the same sender never share an account, whatever their mint.
     #[account(
         init,
-        space = 8 + 174,
+        space = DATA_STORAGE_SPACE,
         payer = payer,
         seeds = [
             b"data_store".as_ref(),
             &sender, 
-            &[current_count]
+            current_count.to_be_bytes().as_ref()
         ],
         bump,
     )]
@@ -591,7 +1192,7 @@ pub struct StoreMsg<'info>{
     #[account(
         init_if_needed,
         payer = payer, 
-        space = 8 + 4,
+        space = TXN_COUNT_SPACE,
         seeds = [
             b"txn_count".as_ref(),
             &sender,
@@ -603,32 +1204,91 @@ pub struct StoreMsg<'info>{
     #[account(
         init, 
         payer = payer,
-        space = 8 + 1 + 1,
+        space = TXN_STATUS_SPACE,
         seeds = [
             b"txn_status".as_ref(),
             &sender,
-            &[current_count]
+            current_count.to_be_bytes().as_ref()
         ],
         bump
     )]
     pub txn_status: Account<'info, TransactionStatus>,
+
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = DAILY_STATS_SPACE,
+        seeds = [b"daily_stats".as_ref(), current_day()?.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub daily_stats: Account<'info, DailyStats>,
+
+    // DailyStats of config.last_stats_day, rolled up when today is a later day.
+    #[account(
+        seeds = [b"daily_stats".as_ref(), config.last_stats_day.to_le_bytes().as_ref()],
+        bump
+    )]
+    /// CHECK: only read when it holds a DailyStats, a day without messages has none
+    pub previous_daily_stats: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct StoreMsgBatch<'info>{
+
+    // ZEBEC's EOA.
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump,
+    )]
+    pub config: Account<'info, Config>,
+
+    pub emitter_acc: Account<'info, EmitterAddrAccount>,
+
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = DAILY_STATS_SPACE,
+        seeds = [b"daily_stats".as_ref(), current_day()?.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub daily_stats: Account<'info, DailyStats>,
+
+    // DailyStats of config.last_stats_day, rolled up when today is a later day.
+    #[account(
+        seeds = [b"daily_stats".as_ref(), config.last_stats_day.to_le_bytes().as_ref()],
+        bump
+    )]
+    /// CHECK: only read when it holds a DailyStats, a day without messages has none
+    pub previous_daily_stats: AccountInfo<'info>,
 }
 
 #[derive(Accounts)]
 #[instruction(  
     eth_add:[u8; 32],
     from_chain_id: Vec<u8>,
-    current_count: u8
+    current_count: u64
 )]
 pub struct ExecuteTransaction<'info> {
     pub system_program: Program<'info, System>,
+
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump,
+    )]
+    pub config: Account<'info, Config>,
     ///CHECK: seeds are checked while creating transaction,
     /// if different seeds passed the signature will not match
     #[account(
         mut,
         seeds = [
             &eth_add,
-            &from_chain_id
+            chain_id_seed(data_storage.from_chain_id).as_bytes()
         ],
         bump
     )]
@@ -641,9 +1301,21 @@ pub struct ExecuteTransaction<'info> {
         seeds = [
             b"txn_status".as_ref(),
             &eth_add,
-            &[current_count]
+            current_count.to_be_bytes().as_ref()
         ],
         bump
     )]
     pub txn_status: Account<'info, TransactionStatus>,
+
+    #[account(
+        seeds = [
+            b"data_store".as_ref(),
+            &eth_add,
+            current_count.to_be_bytes().as_ref()
+        ],
+        bump,
+        // A transaction only executes against the operation it was built from.
+        constraint = transaction.data_storage == data_storage.key() @ MessengerError::DataAccountMismatch
+    )]
+    pub data_storage: Account<'info, TransactionData>,
 }
@@ -5,7 +5,9 @@ pub enum MessengerError {
     #[msg("Posted VAA Key Mismatch")]
     VAAKeyMismatch,
 
-    #[msg("Posted VAA Emitter Chain ID or Address Mismatch")]
+    // Unused since split into WrongEmitterChain and WrongEmitterAddress, kept so the
+    // codes after it don't shift.
+    #[msg("Deprecated, Emitter Mismatches Are Reported As WrongEmitterChain Or WrongEmitterAddress")]
     VAAEmitterMismatch,
 
     #[msg("Sender Wallet Mismatch")]
@@ -66,8 +68,175 @@ pub enum MessengerError {
     DataAccountMismatch,
 
     #[msg("Transaction Already Created")]
-    TransactionAlreadyCreated,
+    AlreadyCreated,
 
-    #[msg("Transaction Already Executed")]
-    TransactionAlreadyExecuted
+    // Unused since merged into AlreadyExecuted, kept so the codes after it don't shift.
+    #[msg("Deprecated, Transaction Already Executed Is Reported As AlreadyExecuted")]
+    TransactionAlreadyExecuted,
+
+    #[msg("Too Many Open Transactions")]
+    TooManyOpenTransactions,
+
+    #[msg("Invalid Cliff Time")]
+    InvalidCliffTime,
+
+    #[msg("Cliff Time Mismatch")]
+    CliffTimeMismatch,
+
+    #[msg("Start Time Must Be Before End Time")]
+    InvalidTimeRange,
+
+    #[msg("Amount Must Be Greater Than Zero")]
+    ZeroAmount,
+
+    #[msg("Invalid Page Size")]
+    InvalidPageSize,
+
+    #[msg("Insufficient Balance")]
+    InsufficientBalance,
+
+    #[msg("Invalid Batch Size")]
+    InvalidBatchSize,
+
+    #[msg("Operation Expired")]
+    OperationExpired,
+
+    #[msg("Sender And Receiver Are The Same")]
+    SelfTransfer,
+
+    #[msg("Unexpected Program Id")]
+    UnexpectedProgramId,
+
+    #[msg("Transfer Not Staged")]
+    TransferNotStaged,
+
+    #[msg("Invalid Clock Account")]
+    InvalidClockAccount,
+
+    #[msg("Invalid Rent Account")]
+    InvalidRentAccount,
+
+    #[msg("VAA Serialization Failed")]
+    VaaSerializationFailed,
+
+    #[msg("Insufficient Finality")]
+    InsufficientFinality,
+
+    #[msg("Value Out Of Range")]
+    ValueOutOfRange,
+
+    #[msg("Portal Message Account Already Used")]
+    PortalMessageReused,
+
+    #[msg("Amount Changed Since Creation")]
+    AmountChanged,
+
+    #[msg("Decimals Mismatch")]
+    DecimalsMismatch,
+
+    #[msg("Mint Account Not Provided")]
+    MissingMintAccount,
+
+    #[msg("Precision Loss")]
+    PrecisionLoss,
+
+    #[msg("Emitter Account Is For Another Chain")]
+    WrongEmitterChain,
+
+    #[msg("Emitter Address Mismatch")]
+    WrongEmitterAddress,
+
+    #[msg("Malformed VAA")]
+    MalformedVaa,
+
+    // Versioned payloads declare their sender length, see split_version.
+    #[msg("Declared Sender Length Does Not Match Payload")]
+    SenderLengthMismatch,
+
+    #[msg("Stored Operation Does Not Match")]
+    OperationMismatch,
+
+    #[msg("Bridge Is Paused")]
+    BridgePaused,
+
+    #[msg("PDA Signer Does Not Match Stored Operation")]
+    InvalidPdaSigner,
+
+    #[msg("Transaction Accounts Missing From Remaining Accounts")]
+    MissingRemainingAccounts,
+
+    #[msg("Outbound Rate Limit Exceeded")]
+    RateLimitExceeded,
+
+    #[msg("Instruction Does Not Match Relayed Instruction")]
+    InstructionMismatch,
+
+    #[msg("Withdrawer Is Not The Stream Receiver")]
+    WithdrawerMismatch,
+
+    #[msg("Payload Too Large")]
+    PayloadTooLarge,
+
+    #[msg("Mint Not Allowed")]
+    MintNotAllowed,
+
+    #[msg("Too Many Allowed Mints")]
+    TooManyAllowedMints,
+
+    #[msg("Invalid Pubkey Bytes")]
+    InvalidPubkeyBytes,
+
+    #[msg("Stream Data Storage Not Passed")]
+    StreamNotFound,
+
+    #[msg("Operation Not Permitted By The Stream")]
+    OperationNotPermitted,
+
+    #[msg("Unsupported Target Chain")]
+    UnsupportedTargetChain,
+
+    #[msg("Too Many Target Chains")]
+    TooManyTargetChains,
+
+    #[msg("Transaction Not Created")]
+    TransactionNotCreated,
+
+    #[msg("Unsupported Payload Version")]
+    UnsupportedPayloadVersion,
+
+    #[msg("Too Many Registered Chains")]
+    TooManyRegisteredChains,
+
+    #[msg("Wrapped Meta Does Not Match The Token")]
+    WrappedMetaMismatch,
+
+    #[msg("Portal Emitter Or Sequence Does Not Match Config")]
+    PortalAccountMismatch,
+
+    #[msg("Amount Below Minimum Transfer Amount")]
+    AmountBelowMinimum,
+
+    #[msg("Withdraw Amount Exceeds Stream Amount")]
+    WithdrawExceedsStream,
+
+    #[msg("Unknown Opcode")]
+    UnknownOpcode,
+
+    #[msg("Invalid Sweep Account")]
+    InvalidSweepAccount,
+
+    #[msg("VAA Account Not Owned By Core Bridge")]
+    InvalidVaaOwner,
+
+    #[msg("Stream Does Not Allow Updates")]
+    UpdatesNotAllowed,
+
+    #[msg("Transfer Already Staged")]
+    TransferAlreadyStaged,
+
+    #[msg("Transfer Fee Consumes The Whole Amount")]
+    FeeConsumesAmount,
+
+    #[msg("Amount Math Overflow")]
+    MathOverflow,
 }
\ No newline at end of file
@@ -1,40 +1,408 @@
 use anchor_lang::prelude::*;
 
+use crate::state::{ConfigFlag, OperationStage, OperationStatus, TransactionAccount};
+
 #[event]
 pub struct Initialized {
     pub owner: Pubkey,
     pub nonce: u32,
+    pub core_bridge: Pubkey,
+    pub token_bridge: Pubkey,
+    pub bridge_paused: bool,
+}
+
+#[event]
+pub struct RateLimitUpdated {
+    pub max_amount_per_window: u64,
+    pub window_seconds: u64,
+}
+
+#[event]
+pub struct RelayerAuthorityUpdated {
+    pub relayer_authority: Pubkey,
+}
+
+#[event]
+pub struct BridgesUpdated {
+    pub core_bridge: Pubkey,
+    pub token_bridge: Pubkey,
+}
+
+#[event]
+pub struct PortalAccountsUpdated {
+    pub portal_emitter: Pubkey,
+    pub portal_sequence: Pubkey,
+}
+
+// Part of a native amount the token bridge drops, in base units of the mint.
+#[event]
+pub struct DustTruncated {
+    pub mint: Pubkey,
+    pub lost_amount: u64,
+}
+
+#[event]
+pub struct TokensSwept {
+    pub pda_signer: Pubkey,
+    pub source: Pubkey,
+    pub destination: Pubkey,
+    pub mint: Pubkey,
+    pub amount: u64,
+    pub owner: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct RateLimitReset {
+    pub previous_amount: u64,
+}
+
+#[event]
+pub struct BatchComputeBudgetUpdated {
+    pub batch_compute_budget: u64,
+}
+
+#[event]
+pub struct BatchStopped {
+    pub processed: u8,
+    pub remaining: u8,
+}
+
+#[event]
+pub struct MaxOpenTransactionsUpdated {
+    pub max_open_transactions: u64,
+}
+
+#[event]
+pub struct MinConsistencyLevelUpdated {
+    pub min_consistency_level: u8,
+}
+
+// Values after update_risk_params, changed or not.
+#[event]
+pub struct RiskParamsUpdated {
+    pub min_consistency_level: u8,
+    pub max_open_transactions: u64,
+    pub batch_compute_budget: u64,
+    pub max_amount_per_window: u64,
+    pub window_seconds: u64,
+    pub min_transfer_amount: u64,
+}
+
+#[event]
+pub struct ConfigFlagUpdated {
+    pub flag: ConfigFlag,
+    pub enabled: bool,
 }
 
 #[event]
 pub struct RegisteredChain {
     pub chain_id: u16,
     pub emitter_addr: String,
+    pub native_chain_id: u64,
+    pub owner: Pubkey,
+    pub timestamp: i64,
+}
+
+// Emitted at every stage of an operation alongside the stage specific event, so a
+// single subscription can follow it from store to execution.
+#[event]
+pub struct OperationLifecycle {
+    pub operation_id: Pubkey,
+    pub stage: OperationStage,
+    pub status: OperationStatus,
+}
+
+#[event]
+pub struct CpiFailed {
+    pub program_id: Pubkey,
+    pub error_code: u64,
+}
+
+// Logged in the failing transaction, so it shows up in its logs even though the
+// instruction is rejected.
+// Saves clients from re-deriving the posted VAA key with their own serialization.
+#[event]
+pub struct ExpectedPostedVaa {
+    pub key: Pubkey,
+}
+
+// Result of get_status.
+#[event]
+pub struct StatusView {
+    pub owner: Pubkey,
+    pub nonce: u32,
+    pub core_bridge: Pubkey,
+    pub token_bridge: Pubkey,
+    pub bridge_paused: bool,
+    pub open_transactions: u64,
+    pub max_open_transactions: u64,
+    pub min_consistency_level: u8,
+    pub registered_chains: u16,
+    pub bridge_fee: u64,
+    pub min_transfer_amount: u64,
+}
+
+#[event]
+pub struct AllowedMintAdded {
+    pub mint: Pubkey,
+}
+
+#[event]
+pub struct AllowedMintRemoved {
+    pub mint: Pubkey,
+}
+
+#[event]
+pub struct TargetChainAdded {
+    pub chain_id: u16,
+}
+
+#[event]
+pub struct TargetChainRemoved {
+    pub chain_id: u16,
+}
+
+#[event]
+pub struct NextSequence {
+    pub emitter: Pubkey,
+    pub sequence: u64,
+}
+
+// What execute_transaction would invoke for a staged Transaction.
+#[event]
+pub struct TransactionView {
+    pub transaction: Pubkey,
+    pub program_id: Pubkey,
+    pub accounts: Vec<TransactionAccount>,
+    pub data: Vec<u8>,
+    pub did_execute: bool,
+    pub payer: Pubkey,
+    pub data_storage: Pubkey,
+}
+
+// Result of decode_only, the fields store_msg would have written to DataStorage.
+#[event]
+pub struct DataStorageView {
+    pub msg_type: u8,
+    pub sender: Vec<u8>,
+    pub receiver: Vec<u8>,
+    pub data_account: Pubkey,
+    pub from_chain_id: u64,
+    pub token_mint: Pubkey,
+    pub amount: u64,
+    pub start_time: u64,
+    pub end_time: u64,
+    pub can_update: bool,
+    pub can_cancel: bool,
+    pub cliff_time: u64,
+    pub deadline: u64,
+    pub program_id: Pubkey,
+    pub decimals: Option<u8>,
+    pub native_chain_id: u64,
+}
+
+// Decoded payload parameters, emitted by the process_* parsers. Amounts are in
+// mint units, after rescaling from the source chain's decimals.
+#[event]
+pub struct DepositParsed {
+    pub sender: Vec<u8>,
+    pub token_mint: Pubkey,
+    pub amount: u64,
+    pub deadline: u64,
+}
+
+// Stream and StreamWithCliff, cliff_time is 0 for the former.
+#[event]
+pub struct StreamParsed {
+    pub msg_type: u8,
+    pub sender: Vec<u8>,
+    pub receiver: Vec<u8>,
+    pub token_mint: Pubkey,
+    pub amount: u64,
+    pub start_time: u64,
+    pub end_time: u64,
+    pub cliff_time: u64,
+    pub can_update: bool,
+    pub can_cancel: bool,
+    pub deadline: u64,
+}
+
+#[event]
+pub struct StreamUpdateParsed {
+    pub sender: Vec<u8>,
+    pub receiver: Vec<u8>,
+    pub token_mint: Pubkey,
+    pub data_account: Pubkey,
+    pub amount: u64,
+    pub start_time: u64,
+    pub end_time: u64,
+    pub deadline: u64,
+}
+
+// Pause, WithdrawStream and CancelStream, only WithdrawStream carries an amount.
+#[event]
+pub struct StreamActionParsed {
+    pub msg_type: u8,
+    pub sender: Vec<u8>,
+    pub receiver: Vec<u8>,
+    pub token_mint: Pubkey,
+    pub data_account: Pubkey,
+    pub amount: u64,
+    pub deadline: u64,
+}
+
+#[event]
+pub struct WithdrawParsed {
+    pub sender: Vec<u8>,
+    pub token_mint: Pubkey,
+    pub amount: u64,
+    pub deadline: u64,
+}
+
+#[event]
+pub struct RelayParsed {
+    pub sender: Vec<u8>,
+    pub program_id: Pubkey,
+    pub instruction_hash: [u8; 32],
+}
+
+// InstantTransfer and DirectTransfer.
+#[event]
+pub struct TransferParsed {
+    pub msg_type: u8,
+    pub sender: Vec<u8>,
+    pub receiver: Vec<u8>,
+    pub token_mint: Pubkey,
+    pub amount: u64,
+    pub deadline: u64,
+    pub decimals: Option<u8>,
+}
+
+#[event]
+pub struct VaaVerified {
+    pub vaa_key: Pubkey,
+    pub vaa_hash: [u8; 32],
+}
+
+#[event]
+pub struct UnsupportedCode {
+    pub code: u64,
+    pub emitter_chain: u16,
+}
+
+#[event]
+pub struct ConfigMigrated {
+    pub space: u64,
+}
+
+#[event]
+pub struct TxnCountMigrated {
+    pub sender: [u8; 32],
+    pub count: u64,
+}
+
+#[event]
+pub struct LegacyOperationMigrated {
+    pub sender: [u8; 32],
+    pub count: u64,
+    pub data_storage: Pubkey,
+    pub txn_status: Pubkey,
+}
+
+#[event]
+pub struct DataStorageMigrated {
+    pub data_storage: Pubkey,
+    pub from_version: u8,
+    pub to_version: u8,
+}
+
+#[event]
+pub struct TransactionCancelled {
+    pub transaction: Pubkey,
+}
+
+#[event]
+pub struct TxnCountReset {
+    pub sender: [u8; 32],
+    pub previous: u64,
+    pub count: u64,
+}
+
+// Totals of a day once it is over, volume sums the per mint volumes of its
+// DailyStats in base units.
+#[event]
+pub struct DailyRollup {
+    pub day: u64,
+    pub count: u64,
+    pub volume: u64,
+}
+
+#[event]
+pub struct SourceDecimalsUpdated {
+    pub chain_id: u16,
+    pub source_decimals: u8,
+    pub owner: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct NativeChainIdUpdated {
+    pub chain_id: u16,
+    pub native_chain_id: u64,
+    pub owner: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ChainDeregistered {
+    pub chain_id: u16,
+    pub owner: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct RegisteredChains {
+    pub chains: Vec<u16>,
+}
+
+#[event]
+pub struct ChainUpdated {
+    pub chain_id: u16,
+    pub emitter_addr: String,
+    pub owner: Pubkey,
+    pub timestamp: i64,
 }
 
 #[event]
 pub struct StoredMsg {
     pub msg_type: u64,
     pub sender: [u8; 32],
-    pub count: u8,
+    pub count: u64,
 }
 
 #[event]
 pub struct Deposited {
     pub sender: [u8; 32],
-    pub current_count: u8,
+    pub current_count: u64,
 }
 
 #[event]
 pub struct StreamUpdated {
     pub sender: [u8; 32],
-    pub current_count: u8,
+    pub current_count: u64,
 }
 
 #[event]
 pub struct PausedResumed {
     pub sender: [u8; 32],
-    pub current_count: u8,
+    pub current_count: u64,
+}
+
+#[event]
+pub struct DirectTransferStaged {
+    pub sender: [u8; 32],
+    pub current_count: u64,
 }
 
 #[event]
@@ -43,7 +411,9 @@ pub struct DirectTransferredNative {
     pub sender_chain: Vec<u8>,
     pub target_chain: u16,
     pub receiver: Vec<u8>,
-    pub current_count: u8
+    pub current_count: u64,
+    // Message account the bridge posts the transfer to, for relayers to pick up.
+    pub portal_message: Pubkey,
 }
 
 #[event]
@@ -52,37 +422,45 @@ pub struct DirectTransferredWrapped {
     pub sender_chain: Vec<u8>,
     pub target_chain: u16,
     pub receiver: Vec<u8>,
-    pub current_count: u8,
+    pub current_count: u64,
+    // Message account the bridge posts the transfer to, for relayers to pick up.
+    pub portal_message: Pubkey,
 }
 
 #[event]
 pub struct StreamCreated {
     pub sender: [u8; 32],
-    pub current_count: u8,
+    pub current_count: u64,
 }
 
 #[event]
 pub struct CancelCreated {
     pub sender: [u8; 32],
-    pub current_count: u8,
+    pub current_count: u64,
 }
 
 #[event]
 pub struct SenderWithdrawCreated {
     pub sender: [u8; 32],
-    pub current_count: u8,
+    pub current_count: u64,
+}
+
+#[event]
+pub struct RelayCreated {
+    pub sender: [u8; 32],
+    pub current_count: u64,
 }
 
 #[event]
 pub struct InstantTransferCreated {
     pub sender: [u8; 32],
-    pub current_count: u8,
+    pub current_count: u64,
 }
 
 #[event]
 pub struct ReceiverWithdrawCreated {
     pub sender: [u8; 32],
-    pub current_count: u8,
+    pub current_count: u64,
 }
 
 #[event]
@@ -91,3 +469,87 @@ pub struct ExecutedTransaction {
     pub eth_add: [u8; 32],
     pub transaction: Pubkey,
 }
+
+#[event]
+pub struct SuspiciousAmount {
+    pub token_mint: Pubkey,
+    pub amount: u64,
+    pub supply: u64,
+}
+
+#[event]
+pub struct StateExport {
+    pub page: u32,
+    pub data: Vec<u8>,
+}
+
+// Every field of Config, keep it in sync when fields are appended there.
+#[event]
+pub struct ConfigSnapshot {
+    pub schema_version: u8,
+    pub owner: Pubkey,
+    pub nonce: u32,
+    pub core_bridge: Pubkey,
+    pub token_bridge: Pubkey,
+    pub open_transactions: u64,
+    pub max_open_transactions: u64,
+    pub min_consistency_level: u8,
+    pub check_amount_plausibility: bool,
+    pub cap_approval_to_balance: bool,
+    pub reject_self_transfer: bool,
+    pub check_program_id: bool,
+    pub two_phase_direct_transfer: bool,
+    pub enforce_amount_binding: bool,
+    pub log_rejected_codes: bool,
+    pub allow_precision_loss: bool,
+    pub bridge_paused: bool,
+    pub check_pda_signer: bool,
+    pub batch_compute_budget: u64,
+    pub max_amount_per_window: u64,
+    pub window_seconds: u64,
+    pub allowed_mints: Vec<Pubkey>,
+    pub target_chains: Vec<u16>,
+    pub registered_chains: Vec<u16>,
+    pub relayer_authority: Pubkey,
+    pub portal_emitter: Pubkey,
+    pub portal_sequence: Pubkey,
+    pub min_transfer_amount: u64,
+    pub last_stats_day: u64,
+}
+
+// Every field of TransactionData, keep it in sync when fields are appended there.
+#[event]
+pub struct DataStorageSnapshot {
+    pub schema_version: u8,
+    pub index: u32,
+    pub data_storage: Pubkey,
+    pub sender: Vec<u8>,
+    pub receiver: Vec<u8>,
+    pub data_account: Pubkey,
+    pub from_chain_id: u64,
+    pub token_mint: Pubkey,
+    pub amount: u64,
+    pub start_time: u64,
+    pub end_time: u64,
+    pub can_update: bool,
+    pub can_cancel: bool,
+    pub cliff_time: u64,
+    pub deadline: u64,
+    pub program_id: Pubkey,
+    pub msg_type: u8,
+    pub decimals: Option<u8>,
+    pub version: u8,
+    pub refund_amount: u64,
+    pub instruction_hash: [u8; 32],
+    pub cancel_by_receiver: bool,
+    pub native_chain_id: u64,
+    pub staged_target_chain: u16,
+    pub staged_fee: u64,
+}
+
+#[event]
+pub struct PartialApproval {
+    pub from: Pubkey,
+    pub requested: u64,
+    pub approved: u64,
+}
@@ -1,22 +1,30 @@
+// Every handler returns anchor's Result, and fields are written out in full.
+#![allow(clippy::result_large_err, clippy::redundant_field_names)]
+
 use anchor_lang::prelude::*;
 use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::Discriminator;
 
 use anchor_lang::solana_program;
-use anchor_spl::token::{approve, Approve};
+use anchor_spl::token::{
+    approve, close_account, initialize_account, revoke, transfer, Approve, CloseAccount,
+    InitializeAccount, Mint, Revoke, TokenAccount, Transfer,
+};
 
 use primitive_types::U256;
 use sha3::Digest;
 
 use std::collections::BTreeMap;
 
-use byteorder::{BigEndian, WriteBytesExt};
-use hex::decode;
-use std::io::{Cursor, Write};
-use std::str::FromStr;
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use std::io::{Cursor, Read, Write};
 mod constants;
 mod context;
 mod errors;
 mod events;
+mod payload;
+#[cfg(feature = "client")]
+pub mod payload_builder;
 mod portal;
 mod state;
 mod wormhole;
@@ -25,12 +33,11 @@ use constants::*;
 use context::*;
 use errors::*;
 use events::*;
+use payload::PayloadVersion;
 use portal::*;
 use state::*;
 use wormhole::*;
 
-use std::ops::Deref;
-
 use anchor_lang::solana_program::program::invoke_signed;
 
 declare_id!("GtyAQgcYTGso352pgR7T8tfESe3TGE5eUkEj9dYyrypS");
@@ -40,101 +47,966 @@ pub mod solana_project {
 
     use super::*;
 
-    pub fn initialize(ctx: Context<Initialize>) -> Result<()> {
+    pub fn initialize(
+        ctx: Context<Initialize>,
+        core_bridge: Pubkey,
+        token_bridge: Pubkey,
+    ) -> Result<()> {
         ctx.accounts.config.owner = ctx.accounts.owner.key();
         ctx.accounts.config.nonce = 1;
+        ctx.accounts.config.core_bridge = core_bridge;
+        ctx.accounts.config.token_bridge = token_bridge;
 
         emit!(Initialized {
             owner: ctx.accounts.config.owner,
-            nonce: ctx.accounts.config.nonce
+            nonce: ctx.accounts.config.nonce,
+            core_bridge: core_bridge,
+            token_bridge: token_bridge,
+            bridge_paused: ctx.accounts.config.bridge_paused
         });
         Ok(())
     }
 
-    pub fn register_chain(
-        ctx: Context<RegisterChain>,
-        chain_id: u16,
-        emitter_addr: String,
+    pub fn set_max_open_transactions(
+        ctx: Context<UpdateConfig>,
+        max_open_transactions: u64,
+    ) -> Result<()> {
+        ctx.accounts.config.max_open_transactions = max_open_transactions;
+
+        emit!(MaxOpenTransactionsUpdated {
+            max_open_transactions: max_open_transactions
+        });
+        Ok(())
+    }
+
+    pub fn set_batch_compute_budget(
+        ctx: Context<UpdateConfig>,
+        batch_compute_budget: u64,
+    ) -> Result<()> {
+        ctx.accounts.config.batch_compute_budget = batch_compute_budget;
+
+        emit!(BatchComputeBudgetUpdated {
+            batch_compute_budget: batch_compute_budget
+        });
+        Ok(())
+    }
+
+    // Pass Pubkey::default() to leave transfers to the owner alone again.
+    pub fn set_relayer_authority(
+        ctx: Context<UpdateConfig>,
+        relayer_authority: Pubkey,
+    ) -> Result<()> {
+        ctx.accounts.config.relayer_authority = relayer_authority;
+
+        emit!(RelayerAuthorityUpdated {
+            relayer_authority: relayer_authority
+        });
+        Ok(())
+    }
+
+    // Move tokens stuck in an account owned by a foreign wallet's PDA to the owner.
+    // from_chain_id is the Wormhole chain the wallet sends from, see chain_id_seed.
+    pub fn sweep_tokens(
+        ctx: Context<SweepTokens>,
+        sender: [u8; 32],
+        from_chain_id: u64,
+        amount: u64,
+    ) -> Result<()> {
+        require!(amount > 0, MessengerError::ZeroAmount);
+
+        let bump = ctx.bumps.get("pda_signer").unwrap().to_le_bytes();
+        let chain_seed = chain_id_seed(from_chain_id);
+        let signer_seeds: &[&[&[u8]]] = &[&[&sender, chain_seed.as_bytes(), &bump]];
+        transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.source.to_account_info(),
+                    to: ctx.accounts.destination.to_account_info(),
+                    authority: ctx.accounts.pda_signer.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            amount,
+        )?;
+
+        emit!(TokensSwept {
+            pda_signer: ctx.accounts.pda_signer.key(),
+            source: ctx.accounts.source.key(),
+            destination: ctx.accounts.destination.key(),
+            mint: ctx.accounts.source.mint,
+            amount: amount,
+            owner: ctx.accounts.owner.key(),
+            timestamp: Clock::get()?.unix_timestamp
+        });
+        Ok(())
+    }
+
+    // Point the program at other Wormhole deployments. The pinned portal accounts
+    // belong to the old token bridge, so they are cleared until set_portal_accounts
+    // runs again.
+    pub fn set_bridges(
+        ctx: Context<UpdateConfig>,
+        core_bridge: Pubkey,
+        token_bridge: Pubkey,
+    ) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        config.core_bridge = core_bridge;
+        config.token_bridge = token_bridge;
+        config.portal_emitter = Pubkey::default();
+        config.portal_sequence = Pubkey::default();
+
+        emit!(BridgesUpdated {
+            core_bridge: core_bridge,
+            token_bridge: token_bridge
+        });
+        Ok(())
+    }
+
+    // Derive the token bridge emitter and its sequence from the bridges in config and
+    // pin them, e.g. again after the bridges were changed.
+    pub fn set_portal_accounts(ctx: Context<UpdateConfig>) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        let (portal_emitter, _) = Pubkey::find_program_address(&[b"emitter"], &config.token_bridge);
+        let (portal_sequence, _) = Pubkey::find_program_address(
+            &[b"Sequence", portal_emitter.as_ref()],
+            &config.core_bridge,
+        );
+        config.portal_emitter = portal_emitter;
+        config.portal_sequence = portal_sequence;
+
+        emit!(PortalAccountsUpdated {
+            portal_emitter: portal_emitter,
+            portal_sequence: portal_sequence
+        });
+        Ok(())
+    }
+
+    pub fn set_rate_limit(
+        ctx: Context<UpdateConfig>,
+        max_amount_per_window: u64,
+        window_seconds: u64,
     ) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        config.max_amount_per_window = max_amount_per_window;
+        config.window_seconds = window_seconds;
+
+        emit!(RateLimitUpdated {
+            max_amount_per_window: max_amount_per_window,
+            window_seconds: window_seconds
+        });
+        Ok(())
+    }
+
+    // Once a mint is added, payloads referencing any other mint are rejected.
+    pub fn add_mint(ctx: Context<UpdateConfig>, mint: Pubkey) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        if !config.allowed_mints.contains(&mint) {
+            require!(
+                config.allowed_mints.len() < MAX_ALLOWED_MINTS,
+                MessengerError::TooManyAllowedMints
+            );
+            config.allowed_mints.push(mint);
+        }
+
+        emit!(AllowedMintAdded { mint: mint });
+        Ok(())
+    }
+
+    // Removing the last mint goes back to accepting any mint.
+    pub fn remove_mint(ctx: Context<UpdateConfig>, mint: Pubkey) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        config.allowed_mints.retain(|allowed| *allowed != mint);
+
+        emit!(AllowedMintRemoved { mint: mint });
+        Ok(())
+    }
+
+    // Once a chain is added, transfers to any other chain are rejected.
+    pub fn add_target_chain(ctx: Context<UpdateConfig>, chain_id: u16) -> Result<()> {
         require!(
-            emitter_addr.len() == EVM_CHAIN_ADDRESS_LENGTH,
-            MessengerError::InvalidEmitterAddress
+            chain_id != 0 && chain_id != CHAIN_ID_SOLANA,
+            MessengerError::UnsupportedTargetChain
         );
+        let config = &mut ctx.accounts.config;
+        if !config.target_chains.contains(&chain_id) {
+            require!(
+                config.target_chains.len() < MAX_TARGET_CHAINS,
+                MessengerError::TooManyTargetChains
+            );
+            config.target_chains.push(chain_id);
+        }
 
-        ctx.accounts.emitter_acc.chain_id = chain_id;
-        ctx.accounts.emitter_acc.emitter_addr = emitter_addr.clone();
+        emit!(TargetChainAdded { chain_id: chain_id });
+        Ok(())
+    }
 
-        emit!(RegisteredChain {
-            chain_id: chain_id,
-            emitter_addr: emitter_addr
+    pub fn remove_target_chain(ctx: Context<UpdateConfig>, chain_id: u16) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        config.target_chains.retain(|target| *target != chain_id);
+
+        emit!(TargetChainRemoved { chain_id: chain_id });
+        Ok(())
+    }
+
+    // Lift a tripped rate limit before the window runs out.
+    pub fn reset_rate_limit(ctx: Context<ResetRateLimit>) -> Result<()> {
+        let rate_limit = &mut ctx.accounts.rate_limit;
+        let previous_amount = rate_limit.amount;
+        rate_limit.window_start = 0;
+        rate_limit.amount = 0;
+
+        emit!(RateLimitReset {
+            previous_amount: previous_amount
+        });
+        Ok(())
+    }
+
+    pub fn set_min_consistency_level(
+        ctx: Context<UpdateConfig>,
+        min_consistency_level: u8,
+    ) -> Result<()> {
+        ctx.accounts.config.min_consistency_level = min_consistency_level;
+
+        emit!(MinConsistencyLevelUpdated {
+            min_consistency_level: min_consistency_level
+        });
+        Ok(())
+    }
+
+    // Several risk parameters in one instruction, so they never take effect half updated.
+    pub fn update_risk_params(ctx: Context<UpdateConfig>, params: RiskParams) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        if let Some(min_consistency_level) = params.min_consistency_level {
+            config.min_consistency_level = min_consistency_level;
+        }
+        if let Some(max_open_transactions) = params.max_open_transactions {
+            config.max_open_transactions = max_open_transactions;
+        }
+        if let Some(batch_compute_budget) = params.batch_compute_budget {
+            config.batch_compute_budget = batch_compute_budget;
+        }
+        if let Some(max_amount_per_window) = params.max_amount_per_window {
+            config.max_amount_per_window = max_amount_per_window;
+        }
+        if let Some(window_seconds) = params.window_seconds {
+            config.window_seconds = window_seconds;
+        }
+        if let Some(min_transfer_amount) = params.min_transfer_amount {
+            config.min_transfer_amount = min_transfer_amount;
+        }
+
+        emit!(RiskParamsUpdated {
+            min_consistency_level: config.min_consistency_level,
+            max_open_transactions: config.max_open_transactions,
+            batch_compute_budget: config.batch_compute_budget,
+            max_amount_per_window: config.max_amount_per_window,
+            window_seconds: config.window_seconds,
+            min_transfer_amount: config.min_transfer_amount
+        });
+        Ok(())
+    }
+
+    pub fn set_config_flag(
+        ctx: Context<UpdateConfig>,
+        flag: ConfigFlag,
+        enabled: bool,
+    ) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        match flag {
+            ConfigFlag::CheckAmountPlausibility => config.check_amount_plausibility = enabled,
+            ConfigFlag::CapApprovalToBalance => config.cap_approval_to_balance = enabled,
+            ConfigFlag::RejectSelfTransfer => config.reject_self_transfer = enabled,
+            ConfigFlag::CheckProgramId => config.check_program_id = enabled,
+            ConfigFlag::TwoPhaseDirectTransfer => config.two_phase_direct_transfer = enabled,
+            ConfigFlag::EnforceAmountBinding => config.enforce_amount_binding = enabled,
+            ConfigFlag::LogRejectedCodes => config.log_rejected_codes = enabled,
+            ConfigFlag::AllowPrecisionLoss => config.allow_precision_loss = enabled,
+            ConfigFlag::BridgePaused => config.bridge_paused = enabled,
+            ConfigFlag::CheckPdaSigner => config.check_pda_signer = enabled,
+        }
+
+        emit!(ConfigFlagUpdated {
+            flag: flag,
+            enabled: enabled
+        });
+        Ok(())
+    }
+
+    // Program state for dashboards in one call, emitted as a StatusView.
+    pub fn get_status(ctx: Context<GetStatus>) -> Result<()> {
+        let config = &ctx.accounts.config;
+        // Fee the core bridge charges for every message a transfer posts.
+        let bridge_fee = BridgeData::try_from_slice(&ctx.accounts.bridge_config.data.borrow())
+            .map_err(|_| MessengerError::InvalidPayload)?
+            .config
+            .fee;
+        emit!(StatusView {
+            owner: config.owner,
+            nonce: config.nonce,
+            core_bridge: config.core_bridge,
+            token_bridge: config.token_bridge,
+            bridge_paused: config.bridge_paused,
+            open_transactions: config.open_transactions,
+            max_open_transactions: config.max_open_transactions,
+            min_consistency_level: config.min_consistency_level,
+            registered_chains: config.registered_chains.len() as u16,
+            bridge_fee: bridge_fee,
+            min_transfer_amount: config.min_transfer_amount
+        });
+        Ok(())
+    }
+
+    // Sequence the next outbound transfer will be posted with, emitted as a
+    // NextSequence so clients don't have to decode the SequenceTracker themselves.
+    pub fn get_next_sequence(ctx: Context<GetNextSequence>) -> Result<()> {
+        // The core bridge only creates the tracker with the first message.
+        let sequence = if ctx.accounts.portal_sequence.data_is_empty() {
+            0
+        } else {
+            SequenceTracker::try_from_slice(&ctx.accounts.portal_sequence.data.borrow())
+                .map_err(|_| MessengerError::InvalidPayload)?
+                .sequence
+        };
+        emit!(NextSequence {
+            emitter: ctx.accounts.portal_emitter.key(),
+            sequence: sequence
+        });
+        Ok(())
+    }
+
+    // A staged Transaction as execute_transaction would invoke it, emitted as a
+    // TransactionView so relayers can check it before spending the CPI.
+    pub fn get_transaction(ctx: Context<GetTransaction>) -> Result<()> {
+        let tx = &ctx.accounts.transaction;
+        emit!(TransactionView {
+            transaction: tx.key(),
+            program_id: tx.program_id,
+            accounts: tx.accounts.clone(),
+            data: tx.data.clone(),
+            did_execute: tx.did_execute,
+            payer: tx.payer,
+            data_storage: tx.data_storage
+        });
+        Ok(())
+    }
+
+    // Dry run of the payload parsing for integrators building payloads on the EVM
+    // side. `payload` is everything after the opcode, `sender` and `emitter_chain`
+    // stand in for what store_msg would take from the instruction and the VAA.
+    // Nothing is written, the result is emitted as a DataStorageView.
+    pub fn decode_only(
+        ctx: Context<DecodeOnly>,
+        code: u64,
+        payload: Vec<u8>,
+        sender: [u8; 32],
+        emitter_chain: u16,
+    ) -> Result<()> {
+        let code = u8::try_from(code).map_err(|_| MessengerError::ValueOutOfRange)?;
+        let mut encoded_str = Vec::with_capacity(payload.len() + 1);
+        encoded_str.push(code);
+        encoded_str.extend_from_slice(&payload);
+
+        let env = MsgEnv {
+            config: &ctx.accounts.config,
+            remaining_accounts: ctx.remaining_accounts,
+            source_decimals: ctx.accounts.emitter_acc.source_decimals,
+            native_chain_id: ctx.accounts.emitter_acc.native_chain_id,
+        };
+        let mut transaction_data = TransactionData::default();
+        decode_payload(
+            encoded_str,
+            emitter_chain,
+            sender.to_vec(),
+            &mut transaction_data,
+            &env,
+        )?;
+
+        emit!(DataStorageView {
+            msg_type: transaction_data.msg_type,
+            sender: transaction_data.sender,
+            receiver: transaction_data.receiver,
+            data_account: transaction_data.data_account,
+            from_chain_id: transaction_data.from_chain_id,
+            token_mint: transaction_data.token_mint,
+            amount: transaction_data.amount,
+            start_time: transaction_data.start_time,
+            end_time: transaction_data.end_time,
+            can_update: transaction_data.can_update,
+            can_cancel: transaction_data.can_cancel,
+            cliff_time: transaction_data.cliff_time,
+            deadline: transaction_data.deadline,
+            program_id: transaction_data.program_id,
+            decimals: transaction_data.decimals,
+            native_chain_id: transaction_data.native_chain_id
+        });
+        Ok(())
+    }
+
+    // Relayers holding an unposted VAA can use this to find the PostedVAA account
+    // store_msg will expect once the VAA is posted through the core bridge.
+    pub fn expected_posted_vaa(ctx: Context<PostedVaaLookup>, raw_vaa: Vec<u8>) -> Result<()> {
+        let vaa = parse_raw_vaa(&raw_vaa)?;
+        let (key, _) = posted_vaa_key(&ctx.accounts.config, &vaa)?;
+
+        emit!(ExpectedPostedVaa { key: key });
+        Ok(())
+    }
+
+    // Re-emits current state under EVENT_SCHEMA_VERSION so indexers can migrate to a
+    // new event schema without replaying history. DataStorage accounts are paged:
+    // the caller passes `count` of them in remaining_accounts, and `from` is the
+    // index of the first one in the caller's enumeration (e.g. getProgramAccounts
+    // sorted by key). Every snapshot carries its index so pages can be stitched back
+    // together. The config snapshot is emitted once per call.
+    pub fn reindex(ctx: Context<Reindex>, from: u32, count: u32) -> Result<()> {
+        require!(
+            count <= MAX_REINDEX_PAGE_SIZE && count as usize == ctx.remaining_accounts.len(),
+            MessengerError::InvalidPageSize
+        );
+
+        let config = &ctx.accounts.config;
+        emit!(ConfigSnapshot {
+            schema_version: EVENT_SCHEMA_VERSION,
+            owner: config.owner,
+            nonce: config.nonce,
+            core_bridge: config.core_bridge,
+            token_bridge: config.token_bridge,
+            open_transactions: config.open_transactions,
+            max_open_transactions: config.max_open_transactions,
+            min_consistency_level: config.min_consistency_level,
+            check_amount_plausibility: config.check_amount_plausibility,
+            cap_approval_to_balance: config.cap_approval_to_balance,
+            reject_self_transfer: config.reject_self_transfer,
+            check_program_id: config.check_program_id,
+            two_phase_direct_transfer: config.two_phase_direct_transfer,
+            enforce_amount_binding: config.enforce_amount_binding,
+            log_rejected_codes: config.log_rejected_codes,
+            allow_precision_loss: config.allow_precision_loss,
+            bridge_paused: config.bridge_paused,
+            check_pda_signer: config.check_pda_signer,
+            batch_compute_budget: config.batch_compute_budget,
+            max_amount_per_window: config.max_amount_per_window,
+            window_seconds: config.window_seconds,
+            allowed_mints: config.allowed_mints.clone(),
+            target_chains: config.target_chains.clone(),
+            registered_chains: config.registered_chains.clone(),
+            relayer_authority: config.relayer_authority,
+            portal_emitter: config.portal_emitter,
+            portal_sequence: config.portal_sequence,
+            min_transfer_amount: config.min_transfer_amount,
+            last_stats_day: config.last_stats_day
+        });
+
+        for (i, acc) in ctx.remaining_accounts.iter().enumerate() {
+            let data_storage = Account::<TransactionData>::try_from(acc)?;
+            let index = from.checked_add(i as u32);
+            let index = match index {
+                None => return Err(MessengerError::Overflow.into()),
+                Some(val) => val,
+            };
+
+            emit!(DataStorageSnapshot {
+                schema_version: EVENT_SCHEMA_VERSION,
+                index: index,
+                data_storage: acc.key(),
+                sender: data_storage.sender.clone(),
+                receiver: data_storage.receiver.clone(),
+                data_account: data_storage.data_account,
+                from_chain_id: data_storage.from_chain_id,
+                token_mint: data_storage.token_mint,
+                amount: data_storage.amount,
+                start_time: data_storage.start_time,
+                end_time: data_storage.end_time,
+                can_update: data_storage.can_update,
+                can_cancel: data_storage.can_cancel,
+                cliff_time: data_storage.cliff_time,
+                deadline: data_storage.deadline,
+                program_id: data_storage.program_id,
+                msg_type: data_storage.msg_type,
+                decimals: data_storage.decimals,
+                version: data_storage.version,
+                refund_amount: data_storage.refund_amount,
+                instruction_hash: data_storage.instruction_hash,
+                cancel_by_receiver: data_storage.cancel_by_receiver,
+                native_chain_id: data_storage.native_chain_id,
+                staged_target_chain: data_storage.staged_target_chain,
+                staged_fee: data_storage.staged_fee
+            });
+        }
+        Ok(())
+    }
+
+    // Backup of the config and a page of emitter accounts (passed in remaining_accounts)
+    // as a single borsh encoded ExportedState.
+    pub fn export_state(ctx: Context<ExportState>, page: u32) -> Result<()> {
+        require!(
+            ctx.remaining_accounts.len() <= MAX_EXPORT_PAGE_SIZE,
+            MessengerError::InvalidPageSize
+        );
+
+        let mut emitters = Vec::with_capacity(ctx.remaining_accounts.len());
+        for acc in ctx.remaining_accounts.iter() {
+            let emitter_acc = Account::<EmitterAddrAccount>::try_from(acc)?;
+            emitters.push((acc.key(), emitter_acc.into_inner()));
+        }
+
+        let state = ExportedState {
+            config: ctx.accounts.config.clone().into_inner(),
+            emitters: emitters,
+        };
+
+        emit!(StateExport {
+            page: page,
+            data: state.try_to_vec()?
         });
         Ok(())
     }
 
-    pub fn store_msg(ctx: Context<StoreMsg>, current_count: u8, sender: [u8; 32]) -> Result<()> {
-        //Hash a VAA Extract and derive a VAA Key
-        let vaa = PostedMessageData::try_from_slice(&ctx.accounts.core_bridge_vaa.data.borrow())?.0;
-        let serialized_vaa = serialize_vaa(&vaa);
+    // Repair path for a sender's transaction counter, e.g. one left too high by a
+    // migration. Counters are only kept per sender, there is no global or per chain
+    // counter to reset, so the sender takes the place of the requested chain_id.
+    pub fn reset_txn_count(
+        ctx: Context<ResetTxnCount>,
+        sender: [u8; 32],
+        value: u64,
+    ) -> Result<()> {
+        let previous = reset_count(
+            &mut ctx.accounts.txn_count,
+            value,
+            &ctx.accounts.next_data_storage,
+        )?;
 
-        let mut h = sha3::Keccak256::default();
-        h.write_all(serialized_vaa.as_slice()).unwrap();
-        let vaa_hash: [u8; 32] = h.finalize().into();
+        emit!(TxnCountReset {
+            sender: sender,
+            previous: previous,
+            count: value
+        });
+        Ok(())
+    }
 
-        let (vaa_key, _) = Pubkey::find_program_address(
-            &[b"PostedVAA", &vaa_hash],
-            &Pubkey::from_str(CORE_BRIDGE_ADDRESS).unwrap(),
+    // Grow a DataStorage account created under an older layout to the current one.
+    // Fields are only appended, so the new bytes are zeroed, which decodes as the
+    // defaults of the new fields.
+    // Streams built before data_account was recorded can't be found by find_stream;
+    // stream_data_account backfills it with the Zebec data account they were built with.
+    pub fn migrate_data_storage(
+        ctx: Context<MigrateDataStorage>,
+        stream_data_account: Option<Pubkey>,
+    ) -> Result<()> {
+        let data_storage = ctx.accounts.data_storage.to_account_info();
+        require!(
+            data_storage
+                .try_borrow_data()?
+                .starts_with(&TransactionData::discriminator()),
+            anchor_lang::error::ErrorCode::AccountDiscriminatorMismatch
         );
 
+        grow_account(
+            &ctx.accounts.owner,
+            &ctx.accounts.system_program,
+            &data_storage,
+            DATA_STORAGE_SPACE,
+        )?;
+        let from_version = upgrade_data_storage(&data_storage, stream_data_account)?;
+
+        emit!(DataStorageMigrated {
+            data_storage: data_storage.key(),
+            from_version: from_version,
+            to_version: DATA_STORAGE_VERSION
+        });
+        Ok(())
+    }
+
+    // Grow a Config created before fields were appended to it. The new bytes are
+    // zeroed, which decodes as the defaults (off, unset or empty) of the new fields.
+    pub fn migrate_config(ctx: Context<MigrateConfig>) -> Result<()> {
+        let config = ctx.accounts.config.to_account_info();
         require!(
-            ctx.accounts.core_bridge_vaa.key() == vaa_key,
-            MessengerError::VAAKeyMismatch
+            config
+                .try_borrow_data()?
+                .starts_with(&Config::discriminator()),
+            anchor_lang::error::ErrorCode::AccountDiscriminatorMismatch
         );
+        grow_account(
+            &ctx.accounts.owner,
+            &ctx.accounts.system_program,
+            &config,
+            CONFIG_SPACE,
+        )?;
+
+        // Only readable once grown, a failed check rolls the realloc back.
+        let owner = Account::<Config>::try_from(&config)?.owner;
+        require!(
+            owner == ctx.accounts.owner.key(),
+            MessengerError::InvalidCaller
+        );
+
+        emit!(ConfigMigrated {
+            space: CONFIG_SPACE as u64
+        });
+        Ok(())
+    }
 
-        // Already checked that the SignedVaa is owned by core bridge in account constraint logic
-        // Check that the emitter chain and address match up with the vaa
+    // txn_count used to hold a u8. Borsh integers are little endian, so growing the
+    // account to a u64 keeps the count as it was.
+    pub fn migrate_txn_count(ctx: Context<MigrateTxnCount>, sender: [u8; 32]) -> Result<()> {
+        let txn_count = ctx.accounts.txn_count.to_account_info();
         require!(
-            vaa.emitter_chain == ctx.accounts.emitter_acc.chain_id
-                && vaa.emitter_address
-                    == &decode(&ctx.accounts.emitter_acc.emitter_addr.as_str()).unwrap()[..],
-            MessengerError::VAAEmitterMismatch
+            txn_count
+                .try_borrow_data()?
+                .starts_with(&Count::discriminator()),
+            anchor_lang::error::ErrorCode::AccountDiscriminatorMismatch
         );
+        grow_account(
+            &ctx.accounts.owner,
+            &ctx.accounts.system_program,
+            &txn_count,
+            TXN_COUNT_SPACE,
+        )?;
+
+        emit!(TxnCountMigrated {
+            sender: sender,
+            count: Account::<Count>::try_from(&txn_count)?.count
+        });
+        Ok(())
+    }
+
+    // Operations stored while the count was a u8 live at [.., &[count]]. Copy them to
+    // the count.to_be_bytes() seeds the contexts use now and close the old accounts,
+    // their rent going to the owner. Run migrate_data_storage on the copy afterwards.
+    pub fn migrate_legacy_operation(
+        ctx: Context<MigrateLegacyOperation>,
+        sender: [u8; 32],
+        count: u8,
+    ) -> Result<()> {
+        let count_bytes = (count as u64).to_be_bytes();
+        let moves = [
+            (
+                &ctx.accounts.legacy_data_storage,
+                &ctx.accounts.data_storage,
+                b"data_store".as_ref(),
+                DATA_STORAGE_SPACE,
+            ),
+            (
+                &ctx.accounts.legacy_txn_status,
+                &ctx.accounts.txn_status,
+                b"txn_status".as_ref(),
+                TXN_STATUS_SPACE,
+            ),
+        ];
+        for (legacy, target, prefix, space) in moves {
+            let legacy = legacy.to_account_info();
+            let target = target.to_account_info();
+            create_pda_account(
+                &ctx.accounts.owner,
+                &ctx.accounts.system_program,
+                &target,
+                &[prefix, &sender, count_bytes.as_ref()],
+                space,
+                ctx.program_id,
+            )?;
+            {
+                let old = legacy.try_borrow_data()?;
+                let mut new = target.try_borrow_mut_data()?;
+                let len = old.len().min(new.len());
+                new[..len].copy_from_slice(&old[..len]);
+            }
+            close_legacy_account(&legacy, &ctx.accounts.owner.to_account_info())?;
+        }
+
+        emit!(LegacyOperationMigrated {
+            sender: sender,
+            count: count as u64,
+            data_storage: ctx.accounts.data_storage.key(),
+            txn_status: ctx.accounts.txn_status.key()
+        });
+        Ok(())
+    }
+
+    pub fn register_chain(
+        ctx: Context<RegisterChain>,
+        chain_id: u16,
+        emitter_addr: String,
+        native_chain_id: u64,
+    ) -> Result<()> {
+        let emitter_addr = normalize_emitter_addr(&emitter_addr)?;
 
-        // Encoded String
-        let encoded_str = vaa.payload.clone();
+        ctx.accounts.emitter_acc.chain_id = chain_id;
+        ctx.accounts.emitter_acc.emitter_addr = emitter_addr.clone();
+        ctx.accounts.emitter_acc.emitter_bytes = decode_emitter_addr(&emitter_addr)?;
+        ctx.accounts.emitter_acc.native_chain_id = native_chain_id;
 
-        // Decode Encoded String and Store Value based upon the code sent on message passing
-        let code = get_u8(encoded_str[0..1].to_vec());
+        index_registered_chain(&mut ctx.accounts.config, chain_id)?;
+
+        emit!(RegisteredChain {
+            chain_id: chain_id,
+            emitter_addr: emitter_addr,
+            native_chain_id: native_chain_id,
+            owner: ctx.accounts.owner.key(),
+            timestamp: Clock::get()?.unix_timestamp
+        });
+        Ok(())
+    }
 
-        // Change Transaction Count to Current Count
-        let txn_count = &mut ctx.accounts.txn_count;
-        let sum = txn_count.count.checked_add(1);
+    // register_chain for several chains at once, e.g. on a fresh deployment. Already
+    // registered chains are updated and keep their source_decimals.
+    pub fn register_chains<'info>(
+        ctx: Context<'_, '_, '_, 'info, RegisterChains<'info>>,
+        entries: Vec<ChainRegistration>,
+    ) -> Result<()> {
+        require!(
+            !entries.is_empty()
+                && entries.len() <= MAX_REGISTER_CHAINS_BATCH_SIZE
+                && ctx.remaining_accounts.len() == entries.len(),
+            MessengerError::InvalidBatchSize
+        );
 
-        match sum {
-            None => return Err(MessengerError::Overflow.into()),
-            Some(val) => txn_count.count = val,
+        for (entry, emitter_info) in entries.into_iter().zip(ctx.remaining_accounts.iter()) {
+            let emitter_addr = normalize_emitter_addr(&entry.emitter_addr)?;
+            let chain_id_bytes = entry.chain_id.to_be_bytes();
+            let seeds: &[&[u8]] = &[b"EmitterAddress".as_ref(), chain_id_bytes.as_ref()];
+
+            let source_decimals = if emitter_info.data_is_empty() {
+                create_pda_account(
+                    &ctx.accounts.owner,
+                    &ctx.accounts.system_program,
+                    emitter_info,
+                    seeds,
+                    EMITTER_ACC_SPACE,
+                    ctx.program_id,
+                )?;
+                0
+            } else {
+                let (key, _) = Pubkey::find_program_address(seeds, ctx.program_id);
+                require!(
+                    emitter_info.key() == key,
+                    anchor_lang::error::ErrorCode::ConstraintSeeds
+                );
+                Account::<EmitterAddrAccount>::try_from(emitter_info)?.source_decimals
+            };
+
+            write_account(
+                emitter_info,
+                &EmitterAddrAccount {
+                    chain_id: entry.chain_id,
+                    emitter_addr: emitter_addr.clone(),
+                    source_decimals: source_decimals,
+                    emitter_bytes: decode_emitter_addr(&emitter_addr)?,
+                    native_chain_id: entry.native_chain_id,
+                },
+            )?;
+            index_registered_chain(&mut ctx.accounts.config, entry.chain_id)?;
+
+            emit!(RegisteredChain {
+                chain_id: entry.chain_id,
+                emitter_addr: emitter_addr,
+                native_chain_id: entry.native_chain_id,
+                owner: ctx.accounts.owner.key(),
+                timestamp: Clock::get()?.unix_timestamp
+            });
         }
+        Ok(())
+    }
 
-        // let count_stored = ctx.accounts.txn_count.count;
+    // Stop trusting a chain's emitter. The account is closed, so messages from the
+    // chain fail on the emitter_acc seeds until it is registered again.
+    pub fn deregister_chain(ctx: Context<DeregisterChain>, chain_id: u16) -> Result<()> {
+        ctx.accounts
+            .config
+            .registered_chains
+            .retain(|registered| *registered != chain_id);
 
-        emit!(StoredMsg {
-            msg_type: code,
-            sender: sender,
-            count: current_count
+        emit!(ChainDeregistered {
+            chain_id: chain_id,
+            owner: ctx.accounts.owner.key(),
+            timestamp: Clock::get()?.unix_timestamp
+        });
+        Ok(())
+    }
+
+    // Chains with a registered emitter, emitted as RegisteredChains.
+    pub fn list_chains(ctx: Context<GetStatus>) -> Result<()> {
+        emit!(RegisteredChains {
+            chains: ctx.accounts.config.registered_chains.clone()
+        });
+        Ok(())
+    }
+
+    // Decimals the emitter chain encodes amounts with, 0 to pass amounts through as is.
+    pub fn set_source_decimals(
+        ctx: Context<UpdateChain>,
+        chain_id: u16,
+        source_decimals: u8,
+    ) -> Result<()> {
+        ctx.accounts.emitter_acc.source_decimals = source_decimals;
+
+        emit!(SourceDecimalsUpdated {
+            chain_id: chain_id,
+            source_decimals: source_decimals,
+            owner: ctx.accounts.owner.key(),
+            timestamp: Clock::get()?.unix_timestamp
+        });
+        Ok(())
+    }
+
+    // Native id of a registered chain, e.g. 137 for Polygon, 0 to clear it.
+    pub fn set_native_chain_id(
+        ctx: Context<UpdateChain>,
+        chain_id: u16,
+        native_chain_id: u64,
+    ) -> Result<()> {
+        ctx.accounts.emitter_acc.native_chain_id = native_chain_id;
+
+        emit!(NativeChainIdUpdated {
+            chain_id: chain_id,
+            native_chain_id: native_chain_id,
+            owner: ctx.accounts.owner.key(),
+            timestamp: Clock::get()?.unix_timestamp
+        });
+        Ok(())
+    }
+
+    // Correct the emitter of an already registered chain.
+    pub fn update_chain(
+        ctx: Context<UpdateChain>,
+        chain_id: u16,
+        emitter_addr: String,
+    ) -> Result<()> {
+        let emitter_addr = normalize_emitter_addr(&emitter_addr)?;
+
+        ctx.accounts.emitter_acc.emitter_addr = emitter_addr.clone();
+        ctx.accounts.emitter_acc.emitter_bytes = decode_emitter_addr(&emitter_addr)?;
+
+        emit!(ChainUpdated {
+            chain_id: chain_id,
+            emitter_addr: emitter_addr,
+            owner: ctx.accounts.owner.key(),
+            timestamp: Clock::get()?.unix_timestamp
         });
+        Ok(())
+    }
+
+    pub fn store_msg(ctx: Context<StoreMsg>, current_count: u64, sender: [u8; 32]) -> Result<()> {
+        // current_count is the sender's count once this message is stored: the
+        // create and execute contexts seed data_store and txn_status with the
+        // incremented txn_count, so the accounts are keyed by it here as well.
+        require!(
+            current_count == next_count(&ctx.accounts.txn_count)?,
+            MessengerError::CountMismatch
+        );
+
+        // The context constraint checks the owner too, verify_vaa repeats it so the
+        // check doesn't hinge on the context alone.
+        let vaa = verify_vaa(
+            &ctx.accounts.config,
+            &ctx.accounts.core_bridge_vaa,
+            &ctx.accounts.emitter_acc,
+        )?;
+
+        let env = MsgEnv {
+            config: &ctx.accounts.config,
+            remaining_accounts: ctx.remaining_accounts,
+            source_decimals: ctx.accounts.emitter_acc.source_decimals,
+            native_chain_id: ctx.accounts.emitter_acc.native_chain_id,
+        };
+        dispatch_msg(
+            &vaa,
+            current_count,
+            sender,
+            &mut ctx.accounts.data_storage,
+            &env,
+        )?;
+
+        let day = current_day()?;
+        roll_over_daily_stats(
+            &mut ctx.accounts.config,
+            &ctx.accounts.previous_daily_stats,
+            day,
+        )?;
+        record_daily_stats(
+            &mut ctx.accounts.daily_stats,
+            day,
+            ctx.accounts.data_storage.token_mint,
+            ctx.accounts.data_storage.amount,
+        );
+
+        // Change Transaction Count to Current Count, only once the message was
+        // stored so a rejected payload never advances it.
+        ctx.accounts.txn_count.count = current_count;
+        emit_lifecycle(
+            ctx.accounts.data_storage.key(),
+            OperationStage::Stored,
+            OperationStatus::Succeeded,
+        );
+        Ok(())
+    }
+
+    // Same as store_msg for several VAAs at once. remaining_accounts holds
+    // STORE_MSG_BATCH_ACCOUNTS accounts per message, in order:
+    // [core_bridge_vaa, processed_vaa, data_storage, txn_count, txn_status],
+    // followed by any mints used for the amount plausibility check.
+    // With a batch_compute_budget set, messages are stored until the estimated cost of
+    // the next one would exceed it; BatchStopped tells the relayer what is left over.
+    pub fn store_msg_batch<'info>(
+        ctx: Context<'_, '_, '_, 'info, StoreMsgBatch<'info>>,
+        count: u8,
+        senders: Vec<[u8; 32]>,
+    ) -> Result<()> {
+        let msg_accounts_len = count as usize * STORE_MSG_BATCH_ACCOUNTS;
+        require!(
+            count > 0
+                && count <= MAX_STORE_MSG_BATCH_SIZE
+                && senders.len() == count as usize
+                && ctx.remaining_accounts.len() >= msg_accounts_len,
+            MessengerError::InvalidBatchSize
+        );
+
+        let day = current_day()?;
+        roll_over_daily_stats(
+            &mut ctx.accounts.config,
+            &ctx.accounts.previous_daily_stats,
+            day,
+        )?;
+
+        let (msg_accounts, mint_accounts) = ctx.remaining_accounts.split_at(msg_accounts_len);
+        let env = MsgEnv {
+            config: &ctx.accounts.config,
+            remaining_accounts: mint_accounts,
+            source_decimals: ctx.accounts.emitter_acc.source_decimals,
+            native_chain_id: ctx.accounts.emitter_acc.native_chain_id,
+        };
 
-        // Switch Based on the code
-        match code {
-            2 => process_stream(encoded_str, vaa.emitter_chain, ctx, sender.to_vec()),
-            4 => process_withdraw_stream(encoded_str, vaa.emitter_chain, ctx, sender.to_vec()),
-            6 => process_deposit(encoded_str, vaa.emitter_chain, ctx, sender.to_vec()),
-            8 => process_pause(encoded_str, vaa.emitter_chain, ctx, sender.to_vec()),
-            10 => process_withdraw(encoded_str, vaa.emitter_chain, ctx, sender.to_vec()),
-            12 => process_instant_transfer(encoded_str, vaa.emitter_chain, ctx, sender.to_vec()),
-            14 => process_update_stream(encoded_str, vaa.emitter_chain, ctx, sender.to_vec()),
-            16 => process_cancel_stream(encoded_str, vaa.emitter_chain, ctx, sender.to_vec()),
-            17 => process_direct_transfer(encoded_str, vaa.emitter_chain, ctx, sender.to_vec()),
-            _ => return Err(MessengerError::InvalidPayload.into()),
+        let budget = ctx.accounts.config.batch_compute_budget;
+        let mut spent: u64 = 0;
+        for (processed, (accounts, sender)) in msg_accounts
+            .chunks(STORE_MSG_BATCH_ACCOUNTS)
+            .zip(senders)
+            .enumerate()
+        {
+            let cost = estimate_store_cost(accounts);
+            if budget != 0 && processed > 0 && spent.saturating_add(cost) > budget {
+                emit!(BatchStopped {
+                    processed: processed as u8,
+                    remaining: count - processed as u8
+                });
+                break;
+            }
+            spent = spent.saturating_add(cost);
+
+            store_batched_msg(
+                ctx.program_id,
+                &ctx.accounts.payer,
+                &ctx.accounts.system_program,
+                &ctx.accounts.emitter_acc,
+                accounts,
+                sender,
+                &env,
+                &mut ctx.accounts.daily_stats,
+                day,
+            )?;
         }
+        Ok(())
     }
 
     //creates and executes deposit transaction
@@ -143,18 +1015,23 @@ pub mod solana_project {
         pid: Pubkey,
         accs: Vec<TransactionAccount>,
         data: Vec<u8>,
-        chain_id: Vec<u8>,
+        _chain_id: Vec<u8>,
         sender: [u8; 32],
     ) -> Result<()> {
-        require!(
-            !ctx.accounts.txn_status.executed,
-            MessengerError::TransactionAlreadyCreated
-        );
+        check_creatable(&ctx.accounts.txn_status)?;
+        ctx.accounts.txn_status.created = true;
+        // Created and executed in one go.
+        ctx.accounts.txn_status.executed = true;
+        check_deadline(&ctx.accounts.data_storage)?;
+        check_operation(&ctx.accounts.data_storage, &[MessageCode::Deposit])?;
+        check_program_id(&ctx.accounts.config, &ctx.accounts.data_storage, pid)?;
         //Build Transactions
         let tx = &mut ctx.accounts.transaction;
         tx.program_id = pid;
+        tx.payer = ctx.accounts.zebec_eoa.key();
+        tx.data_storage = ctx.accounts.data_storage.key();
         tx.accounts = accs.clone();
-        tx.data = data.clone();
+        tx.data = data;
 
         let count_stored = ctx.accounts.txn_count.count;
 
@@ -167,25 +1044,25 @@ pub mod solana_project {
 
         //check sender
         let pda_sender_passed: Pubkey = accs[1].pubkey;
-        let sender_stored = ctx.accounts.data_storage.sender.clone();
+        let sender_stored = &ctx.accounts.data_storage.sender;
         require!(
-            sender.to_vec() == sender_stored,
+            sender[..] == sender_stored[..],
             MessengerError::PdaSenderMismatch
         );
 
         //check pdaSender
-        let chain_id_stored = (ctx.accounts.data_storage.from_chain_id).to_string();
-        let chain_id_seed = chain_id_stored.as_bytes();
-        let derived_pubkey: (Pubkey, u8) =
-            Pubkey::find_program_address(&[&sender, &chain_id_seed], ctx.program_id);
+        let derived_pubkey: (Pubkey, u8) = derive_pda_signer(
+            &sender,
+            ctx.accounts.data_storage.from_chain_id,
+            ctx.program_id,
+        );
         require!(
             pda_sender_passed == derived_pubkey.0,
             MessengerError::SenderDerivedKeyMismatch
         );
 
         //check data params passed
-        let data: &[u8] = data.as_slice();
-        let data_slice = &data[8..];
+        let data_slice = &ctx.accounts.transaction.data[8..];
         let decode_data = TokenAmount::try_from_slice(data_slice)?;
         let amount_passed = decode_data.amount;
         require!(
@@ -193,24 +1070,31 @@ pub mod solana_project {
             MessengerError::AmountMismatch
         );
 
+        check_remaining_accounts(&ctx.accounts.transaction, ctx.remaining_accounts)?;
+
         // Burn the transaction to ensure one time use.
-        ctx.accounts.transaction.did_execute = true;
-        require!(
+        burn_transaction(&mut ctx.accounts.transaction)?;
+        check_cpi(
+            ctx.accounts.transaction.program_id,
+            ctx.accounts.data_storage.key(),
             perform_cpi(
-                chain_id.clone(),
-                sender.clone(),
-                *ctx.accounts.transaction.clone(),
-                ctx.accounts.pda_signer.clone(),
+                ctx.accounts.data_storage.from_chain_id,
+                &sender,
+                &ctx.accounts.transaction,
+                &ctx.accounts.pda_signer,
                 ctx.bumps,
-                ctx.remaining_accounts
-            )
-            .is_ok(),
-            MessengerError::InvalidCPI
-        );
+                ctx.remaining_accounts,
+            ),
+        )?;
         emit!(Deposited {
             sender: sender,
             current_count: count_stored
         });
+        emit_lifecycle(
+            ctx.accounts.data_storage.key(),
+            OperationStage::Executed,
+            OperationStatus::Succeeded,
+        );
         Ok(())
     }
 
@@ -224,17 +1108,25 @@ pub mod solana_project {
 
         sender: [u8; 32],
     ) -> Result<()> {
-        require!(
-            !ctx.accounts.txn_status.executed,
-            MessengerError::TransactionAlreadyCreated
-        );
+        check_creatable(&ctx.accounts.txn_status)?;
+        ctx.accounts.txn_status.created = true;
+        check_deadline(&ctx.accounts.data_storage)?;
+        check_operation(
+            &ctx.accounts.data_storage,
+            &[MessageCode::Stream, MessageCode::StreamWithCliff],
+        )?;
+        check_program_id(&ctx.accounts.config, &ctx.accounts.data_storage, pid)?;
 
         //Build Transactions
         let tx = &mut ctx.accounts.transaction;
         tx.program_id = pid;
+        tx.payer = ctx.accounts.zebec_eoa.key();
+        tx.data_storage = ctx.accounts.data_storage.key();
         tx.accounts = accs.clone();
         tx.did_execute = false;
-        tx.data = data.clone();
+        tx.data = data;
+
+        open_transaction(&mut ctx.accounts.config)?;
 
         let count_stored = ctx.accounts.txn_count.count;
 
@@ -247,40 +1139,52 @@ pub mod solana_project {
 
         //check sender
         let pda_sender_passed: Pubkey = accs[5].pubkey;
-        let sender_stored = ctx.accounts.data_storage.sender.clone();
+        let sender_stored = &ctx.accounts.data_storage.sender;
         require!(
-            sender.to_vec() == sender_stored,
+            sender[..] == sender_stored[..],
             MessengerError::PdaSenderMismatch
         );
 
         //check receiver
         let pda_receiver_passed: Pubkey = accs[6].pubkey;
-        let receiver_stored = ctx.accounts.data_storage.receiver.clone();
+        let receiver_stored = &ctx.accounts.data_storage.receiver;
+
+        let chain_seed = chain_id_seed(ctx.accounts.data_storage.from_chain_id);
 
         //check pdaSender
-        let chain_id_stored = (ctx.accounts.data_storage.from_chain_id).to_string();
-        let chain_id_seed = chain_id_stored.as_bytes();
         let sender_derived_pubkey: (Pubkey, u8) =
-            Pubkey::find_program_address(&[&sender, &chain_id_seed], ctx.program_id);
+            derive_pda_signer_with_seed(&sender, chain_seed.as_bytes(), ctx.program_id);
         require!(
             pda_sender_passed == sender_derived_pubkey.0,
             MessengerError::SenderDerivedKeyMismatch
         );
 
         //check pdaReceiver
-        let chain_id_stored = (ctx.accounts.data_storage.from_chain_id).to_string();
-        let chain_id_seed = chain_id_stored.as_bytes();
         let receiver_derived_pubkey: (Pubkey, u8) =
-            Pubkey::find_program_address(&[&receiver_stored, &chain_id_seed], &ctx.program_id);
+            derive_pda_signer_with_seed(receiver_stored, chain_seed.as_bytes(), ctx.program_id);
         require!(
             pda_receiver_passed == receiver_derived_pubkey.0,
             MessengerError::ReceiverDerivedKeyMismatch
         );
 
         //check data params passed
-        let data: &[u8] = data.as_slice();
-        let data_slice = &data[8..];
-        let decode_data = Stream::try_from_slice(data_slice)?;
+        let data_slice = &ctx.accounts.transaction.data[8..];
+        let decode_data = if ctx.accounts.data_storage.cliff_time != 0 {
+            let decode_data = StreamWithCliff::try_from_slice(data_slice)?;
+            require!(
+                decode_data.cliff_time == ctx.accounts.data_storage.cliff_time,
+                MessengerError::CliffTimeMismatch
+            );
+            Stream {
+                start_time: decode_data.start_time,
+                end_time: decode_data.end_time,
+                amount: decode_data.amount,
+                can_cancel: decode_data.can_cancel,
+                can_update: decode_data.can_update,
+            }
+        } else {
+            Stream::try_from_slice(data_slice)?
+        };
         require!(
             decode_data.amount == ctx.accounts.data_storage.amount,
             MessengerError::AmountMismatch
@@ -302,10 +1206,18 @@ pub mod solana_project {
             MessengerError::CanUpdateMismatch
         );
 
+        // Lets a later update or cancel find the flags of this stream.
+        ctx.accounts.data_storage.data_account = accs[0].pubkey;
+
         emit!(StreamCreated {
             sender: sender,
             current_count: count_stored,
         });
+        emit_lifecycle(
+            ctx.accounts.data_storage.key(),
+            OperationStage::Built,
+            OperationStatus::Succeeded,
+        );
         Ok(())
     }
 
@@ -315,18 +1227,26 @@ pub mod solana_project {
         pid: Pubkey,
         accs: Vec<TransactionAccount>,
         data: Vec<u8>,
-        chain_id: Vec<u8>,
+        _chain_id: Vec<u8>,
         sender: [u8; 32],
     ) -> Result<()> {
-        require!(
-            !ctx.accounts.txn_status.executed,
-            MessengerError::TransactionAlreadyCreated
-        );
+        check_creatable(&ctx.accounts.txn_status)?;
+        ctx.accounts.txn_status.created = true;
+        // Created and executed in one go.
+        ctx.accounts.txn_status.executed = true;
+        check_deadline(&ctx.accounts.data_storage)?;
+        check_operation(&ctx.accounts.data_storage, &[MessageCode::UpdateStream])?;
+        check_program_id(&ctx.accounts.config, &ctx.accounts.data_storage, pid)?;
+        let (can_update, _) =
+            stream_permissions(&ctx.accounts.data_storage, ctx.remaining_accounts)?;
+        require!(can_update, MessengerError::UpdatesNotAllowed);
         //Build Transactions
         let tx = &mut ctx.accounts.transaction;
         tx.program_id = pid;
+        tx.payer = ctx.accounts.zebec_eoa.key();
+        tx.data_storage = ctx.accounts.data_storage.key();
         tx.accounts = accs.clone();
-        tx.data = data.clone();
+        tx.data = data;
 
         let count_stored = ctx.accounts.txn_count.count;
 
@@ -346,39 +1266,36 @@ pub mod solana_project {
 
         //check sender
         let pda_sender_passed: Pubkey = accs[2].pubkey;
-        let sender_stored = ctx.accounts.data_storage.sender.clone();
+        let sender_stored = &ctx.accounts.data_storage.sender;
         require!(
-            sender.to_vec() == sender_stored,
+            sender[..] == sender_stored[..],
             MessengerError::PdaSenderMismatch
         );
 
         //check receiver
         let pda_receiver_passed: Pubkey = accs[3].pubkey;
-        let receiver_stored = ctx.accounts.data_storage.receiver.clone();
+        let receiver_stored = &ctx.accounts.data_storage.receiver;
+
+        let chain_seed = chain_id_seed(ctx.accounts.data_storage.from_chain_id);
 
         //check pdaSender
-        let chain_id_stored = (ctx.accounts.data_storage.from_chain_id).to_string();
-        let chain_id_seed = chain_id_stored.as_bytes();
         let sender_derived_pubkey: (Pubkey, u8) =
-            Pubkey::find_program_address(&[&sender, &chain_id_seed], ctx.program_id);
+            derive_pda_signer_with_seed(&sender, chain_seed.as_bytes(), ctx.program_id);
         require!(
             pda_sender_passed == sender_derived_pubkey.0,
             MessengerError::SenderDerivedKeyMismatch
         );
 
         //check pdaReceiver
-        let chain_id_stored = (ctx.accounts.data_storage.from_chain_id).to_string();
-        let chain_id_seed = chain_id_stored.as_bytes();
         let receiver_derived_pubkey: (Pubkey, u8) =
-            Pubkey::find_program_address(&[&receiver_stored, &chain_id_seed], ctx.program_id);
+            derive_pda_signer_with_seed(receiver_stored, chain_seed.as_bytes(), ctx.program_id);
         require!(
             pda_receiver_passed == receiver_derived_pubkey.0,
             MessengerError::ReceiverDerivedKeyMismatch
         );
 
         //check data params passed
-        let data: &[u8] = data.as_slice();
-        let data_slice = &data[8..];
+        let data_slice = &ctx.accounts.transaction.data[8..];
         let decode_data = StreamUpdate::try_from_slice(data_slice)?;
         require!(
             decode_data.amount == ctx.accounts.data_storage.amount,
@@ -392,24 +1309,32 @@ pub mod solana_project {
             decode_data.end_time == ctx.accounts.data_storage.end_time,
             MessengerError::EndTimeMismatch
         );
+
+        check_remaining_accounts(&ctx.accounts.transaction, ctx.remaining_accounts)?;
+
         // Burn the transaction to ensure one time use.
-        ctx.accounts.transaction.did_execute = true;
-        require!(
+        burn_transaction(&mut ctx.accounts.transaction)?;
+        check_cpi(
+            ctx.accounts.transaction.program_id,
+            ctx.accounts.data_storage.key(),
             perform_cpi(
-                chain_id.clone(),
-                sender.clone(),
-                *ctx.accounts.transaction.clone(),
-                ctx.accounts.pda_signer.clone(),
+                ctx.accounts.data_storage.from_chain_id,
+                &sender,
+                &ctx.accounts.transaction,
+                &ctx.accounts.pda_signer,
                 ctx.bumps,
-                ctx.remaining_accounts
-            )
-            .is_ok(),
-            MessengerError::InvalidCPI
-        );
+                ctx.remaining_accounts,
+            ),
+        )?;
         emit!(StreamUpdated {
             sender: sender,
             current_count: count_stored
         });
+        emit_lifecycle(
+            ctx.accounts.data_storage.key(),
+            OperationStage::Executed,
+            OperationStatus::Succeeded,
+        );
         Ok(())
     }
 
@@ -419,16 +1344,21 @@ pub mod solana_project {
         pid: Pubkey,
         accs: Vec<TransactionAccount>,
         data: Vec<u8>,
-        chain_id: Vec<u8>,
+        _chain_id: Vec<u8>,
         sender: [u8; 32],
     ) -> Result<()> {
-        require!(
-            !ctx.accounts.txn_status.executed,
-            MessengerError::TransactionAlreadyCreated
-        );
+        check_creatable(&ctx.accounts.txn_status)?;
+        ctx.accounts.txn_status.created = true;
+        // Created and executed in one go.
+        ctx.accounts.txn_status.executed = true;
+        check_deadline(&ctx.accounts.data_storage)?;
+        check_operation(&ctx.accounts.data_storage, &[MessageCode::Pause])?;
+        check_program_id(&ctx.accounts.config, &ctx.accounts.data_storage, pid)?;
         //Build Transactions
         let tx = &mut ctx.accounts.transaction;
         tx.program_id = pid;
+        tx.payer = ctx.accounts.zebec_eoa.key();
+        tx.data_storage = ctx.accounts.data_storage.key();
         tx.accounts = accs.clone();
         tx.data = data;
 
@@ -443,53 +1373,59 @@ pub mod solana_project {
 
         //check sender
         let pda_sender_passed: Pubkey = accs[0].pubkey;
-        let sender_stored = ctx.accounts.data_storage.sender.clone();
+        let sender_stored = &ctx.accounts.data_storage.sender;
         require!(
-            sender.to_vec() == sender_stored,
+            sender[..] == sender_stored[..],
             MessengerError::PdaSenderMismatch
         );
 
         //check receiver
         let pda_receiver_passed: Pubkey = accs[1].pubkey;
-        let receiver_stored = ctx.accounts.data_storage.receiver.clone();
+        let receiver_stored = &ctx.accounts.data_storage.receiver;
+
+        let chain_seed = chain_id_seed(ctx.accounts.data_storage.from_chain_id);
 
         //check pdaSender
-        let chain_id_stored = (ctx.accounts.data_storage.from_chain_id).to_string();
-        let chain_id_seed = chain_id_stored.as_bytes();
         let sender_derived_pubkey: (Pubkey, u8) =
-            Pubkey::find_program_address(&[&sender, &chain_id_seed], ctx.program_id);
+            derive_pda_signer_with_seed(&sender, chain_seed.as_bytes(), ctx.program_id);
         require!(
             pda_sender_passed == sender_derived_pubkey.0,
             MessengerError::SenderDerivedKeyMismatch
         );
 
         //check pdaReceiver
-        let chain_id_stored = (ctx.accounts.data_storage.from_chain_id).to_string();
-        let chain_id_seed = chain_id_stored.as_bytes();
         let receiver_derived_pubkey: (Pubkey, u8) =
-            Pubkey::find_program_address(&[&receiver_stored, &chain_id_seed], ctx.program_id);
+            derive_pda_signer_with_seed(receiver_stored, chain_seed.as_bytes(), ctx.program_id);
         require!(
             pda_receiver_passed == receiver_derived_pubkey.0,
             MessengerError::ReceiverDerivedKeyMismatch
         );
+
+        check_remaining_accounts(&ctx.accounts.transaction, ctx.remaining_accounts)?;
+
         // Burn the transaction to ensure one time use.
-        ctx.accounts.transaction.did_execute = true;
-        require!(
+        burn_transaction(&mut ctx.accounts.transaction)?;
+        check_cpi(
+            ctx.accounts.transaction.program_id,
+            ctx.accounts.data_storage.key(),
             perform_cpi(
-                chain_id.clone(),
-                sender.clone(),
-                *ctx.accounts.transaction.clone(),
-                ctx.accounts.pda_signer.clone(),
+                ctx.accounts.data_storage.from_chain_id,
+                &sender,
+                &ctx.accounts.transaction,
+                &ctx.accounts.pda_signer,
                 ctx.bumps,
-                ctx.remaining_accounts
-            )
-            .is_ok(),
-            MessengerError::InvalidCPI
-        );
+                ctx.remaining_accounts,
+            ),
+        )?;
         emit!(PausedResumed {
             sender: sender,
             current_count: count_stored
         });
+        emit_lifecycle(
+            ctx.accounts.data_storage.key(),
+            OperationStage::Executed,
+            OperationStatus::Succeeded,
+        );
         Ok(())
     }
 
@@ -500,20 +1436,30 @@ pub mod solana_project {
         pid: Pubkey,
         accs: Vec<TransactionAccount>,
         data: Vec<u8>,
-        sender: [u8; 32],
+        withdrawer: [u8; 32],
     ) -> Result<()> {
-        require!(
-            !ctx.accounts.txn_status.executed,
-            MessengerError::TransactionAlreadyCreated
-        );
+        check_creatable(&ctx.accounts.txn_status)?;
+        ctx.accounts.txn_status.created = true;
+        check_withdraw_caller(
+            &ctx.accounts.config,
+            ctx.accounts.zebec_eoa.key(),
+            &ctx.accounts.data_storage,
+        )?;
+        check_deadline(&ctx.accounts.data_storage)?;
+        check_operation(&ctx.accounts.data_storage, &[MessageCode::WithdrawStream])?;
+        check_program_id(&ctx.accounts.config, &ctx.accounts.data_storage, pid)?;
 
         //Build Transactions
         let tx = &mut ctx.accounts.transaction;
         tx.program_id = pid;
+        tx.payer = ctx.accounts.zebec_eoa.key();
+        tx.data_storage = ctx.accounts.data_storage.key();
         tx.accounts = accs.clone();
         tx.did_execute = false;
         tx.data = data;
 
+        open_transaction(&mut ctx.accounts.config)?;
+
         let count_stored = ctx.accounts.txn_count.count;
 
         //check Mint passed
@@ -532,21 +1478,21 @@ pub mod solana_project {
 
         //check sender
         let pda_sender_passed: Pubkey = accs[2].pubkey;
-        let sender_stored = ctx.accounts.data_storage.sender.clone();
+        let sender_stored = &ctx.accounts.data_storage.sender;
 
-        //check receiver
+        //check receiver, the withdrawer has to be the stream receiver
         let pda_receiver_passed: Pubkey = accs[1].pubkey;
-        let receiver_stored = ctx.accounts.data_storage.receiver.clone();
+        let receiver_stored = &ctx.accounts.data_storage.receiver;
         require!(
-            sender.to_vec() == receiver_stored,
-            MessengerError::PdaReceiverMismatch
+            withdrawer[..] == receiver_stored[..],
+            MessengerError::WithdrawerMismatch
         );
 
+        let chain_seed = chain_id_seed(ctx.accounts.data_storage.from_chain_id);
+
         //check pdaSender
-        let chain_id_stored = (ctx.accounts.data_storage.from_chain_id).to_string();
-        let chain_id_seed = chain_id_stored.as_bytes();
         let sender_derived_pubkey: (Pubkey, u8) =
-            Pubkey::find_program_address(&[&sender_stored, &chain_id_seed], ctx.program_id);
+            derive_pda_signer_with_seed(sender_stored, chain_seed.as_bytes(), ctx.program_id);
         require!(
             pda_sender_passed == sender_derived_pubkey.0,
             MessengerError::SenderDerivedKeyMismatch
@@ -554,16 +1500,38 @@ pub mod solana_project {
 
         //check pdaReceiver
         let receiver_derived_pubkey: (Pubkey, u8) =
-            Pubkey::find_program_address(&[&receiver_stored, &chain_id_seed], ctx.program_id);
+            derive_pda_signer_with_seed(receiver_stored, chain_seed.as_bytes(), ctx.program_id);
         require!(
             pda_receiver_passed == receiver_derived_pubkey.0,
             MessengerError::ReceiverDerivedKeyMismatch
         );
 
+        //check partial amount, a stored amount of 0 withdraws everything vested
+        if ctx.accounts.data_storage.amount != 0 {
+            let data_slice = &ctx.accounts.transaction.data[8..];
+            let decode_data = TokenAmount::try_from_slice(data_slice)?;
+            require!(
+                decode_data.amount == ctx.accounts.data_storage.amount,
+                MessengerError::AmountMismatch
+            );
+
+            // A partial withdrawal can't ask for more than the stream holds.
+            let stream = find_stream(&ctx.accounts.data_storage, ctx.remaining_accounts)?;
+            require!(
+                ctx.accounts.data_storage.amount <= stream.amount,
+                MessengerError::WithdrawExceedsStream
+            );
+        }
+
         emit!(ReceiverWithdrawCreated {
-            sender: sender,
+            sender: withdrawer,
             current_count: count_stored,
         });
+        emit_lifecycle(
+            ctx.accounts.data_storage.key(),
+            OperationStage::Built,
+            OperationStatus::Succeeded,
+        );
         Ok(())
     }
 
@@ -575,70 +1543,106 @@ pub mod solana_project {
         data: Vec<u8>,
         sender: [u8; 32],
     ) -> Result<()> {
+        check_creatable(&ctx.accounts.txn_status)?;
+        ctx.accounts.txn_status.created = true;
+        check_deadline(&ctx.accounts.data_storage)?;
+        check_operation(&ctx.accounts.data_storage, &[MessageCode::CancelStream])?;
+        check_program_id(&ctx.accounts.config, &ctx.accounts.data_storage, pid)?;
+        let (_, can_cancel) =
+            stream_permissions(&ctx.accounts.data_storage, ctx.remaining_accounts)?;
+        require!(can_cancel, MessengerError::OperationNotPermitted);
         require!(
-            !ctx.accounts.txn_status.executed,
-            MessengerError::TransactionAlreadyCreated
+            !ctx.accounts.data_storage.cancel_by_receiver,
+            MessengerError::OperationNotPermitted
         );
 
         //Build Transactions
         let tx = &mut ctx.accounts.transaction;
         tx.program_id = pid;
+        tx.payer = ctx.accounts.zebec_eoa.key();
+        tx.data_storage = ctx.accounts.data_storage.key();
         tx.accounts = accs.clone();
         tx.did_execute = false;
         tx.data = data;
 
+        open_transaction(&mut ctx.accounts.config)?;
+
         let count_stored = ctx.accounts.txn_count.count;
 
-        //check Mint passed
-        let mint_pubkey_passed: Pubkey = accs[12].pubkey;
         require!(
-            mint_pubkey_passed == ctx.accounts.data_storage.token_mint,
-            MessengerError::MintKeyMismatch
+            sender.to_vec() == ctx.accounts.data_storage.sender,
+            MessengerError::PdaSenderMismatch
         );
+        check_cancel(
+            &ctx.accounts.data_storage,
+            &accs,
+            &ctx.accounts.transaction.data,
+            ctx.program_id,
+        )?;
 
-        //check data account
-        let data_account_passed: Pubkey = accs[6].pubkey;
-        require!(
-            data_account_passed == ctx.accounts.data_storage.data_account,
-            MessengerError::DataAccountMismatch
+        emit!(CancelCreated {
+            sender: sender,
+            current_count: count_stored,
+        });
+        emit_lifecycle(
+            ctx.accounts.data_storage.key(),
+            OperationStage::Built,
+            OperationStatus::Succeeded,
         );
+        Ok(())
+    }
 
-        //check sender
-        let pda_sender_passed: Pubkey = accs[2].pubkey;
-        let sender_stored = ctx.accounts.data_storage.sender.clone();
+    // creates transaction cancel for a cancel the receiver signed, stored under the
+    // receiver like a stream withdrawal
+    pub fn create_transaction_receiver_cancel(
+        ctx: Context<CreateTransactionReceiver>,
+        pid: Pubkey,
+        accs: Vec<TransactionAccount>,
+        data: Vec<u8>,
+        receiver: [u8; 32],
+    ) -> Result<()> {
+        check_creatable(&ctx.accounts.txn_status)?;
+        ctx.accounts.txn_status.created = true;
+        check_deadline(&ctx.accounts.data_storage)?;
+        check_operation(&ctx.accounts.data_storage, &[MessageCode::CancelStream])?;
+        check_program_id(&ctx.accounts.config, &ctx.accounts.data_storage, pid)?;
+        let (_, can_cancel) =
+            stream_permissions(&ctx.accounts.data_storage, ctx.remaining_accounts)?;
+        require!(can_cancel, MessengerError::OperationNotPermitted);
         require!(
-            sender.to_vec() == sender_stored,
-            MessengerError::PdaSenderMismatch
+            ctx.accounts.data_storage.cancel_by_receiver,
+            MessengerError::OperationNotPermitted
         );
 
-        //check receiver
-        let pda_receiver_passed: Pubkey = accs[1].pubkey;
-        let receiver_stored = ctx.accounts.data_storage.receiver.clone();
+        //Build Transactions
+        let tx = &mut ctx.accounts.transaction;
+        tx.program_id = pid;
+        tx.payer = ctx.accounts.zebec_eoa.key();
+        tx.data_storage = ctx.accounts.data_storage.key();
+        tx.accounts = accs.clone();
+        tx.did_execute = false;
+        tx.data = data;
 
-        //check pdaSender
-        let chain_id_stored = (ctx.accounts.data_storage.from_chain_id).to_string();
-        let chain_id_seed = chain_id_stored.as_bytes();
-        let sender_derived_pubkey: (Pubkey, u8) =
-            Pubkey::find_program_address(&[&sender, &chain_id_seed], ctx.program_id);
-        require!(
-            pda_sender_passed == sender_derived_pubkey.0,
-            MessengerError::SenderDerivedKeyMismatch
-        );
+        open_transaction(&mut ctx.accounts.config)?;
 
-        //check pdaReceiver
-        let chain_id_stored = (ctx.accounts.data_storage.from_chain_id).to_string();
-        let chain_id_seed = chain_id_stored.as_bytes();
-        let receiver_derived_pubkey: (Pubkey, u8) =
-            Pubkey::find_program_address(&[&receiver_stored, &chain_id_seed], ctx.program_id);
-        require!(
-            pda_receiver_passed == receiver_derived_pubkey.0,
-            MessengerError::ReceiverDerivedKeyMismatch
-        );
+        let count_stored = ctx.accounts.txn_count.count;
+
+        check_cancel(
+            &ctx.accounts.data_storage,
+            &accs,
+            &ctx.accounts.transaction.data,
+            ctx.program_id,
+        )?;
 
         emit!(CancelCreated {
-            sender: sender,
+            sender: receiver,
             current_count: count_stored,
         });
+        emit_lifecycle(
+            ctx.accounts.data_storage.key(),
+            OperationStage::Built,
+            OperationStatus::Succeeded,
+        );
         Ok(())
     }
 
@@ -651,17 +1655,22 @@ pub mod solana_project {
 
         sender: [u8; 32],
     ) -> Result<()> {
-        require!(
-            !ctx.accounts.txn_status.executed,
-            MessengerError::TransactionAlreadyCreated
-        );
+        check_creatable(&ctx.accounts.txn_status)?;
+        ctx.accounts.txn_status.created = true;
+        check_deadline(&ctx.accounts.data_storage)?;
+        check_operation(&ctx.accounts.data_storage, &[MessageCode::Withdraw])?;
+        check_program_id(&ctx.accounts.config, &ctx.accounts.data_storage, pid)?;
 
         //Build Transactions
         let tx = &mut ctx.accounts.transaction;
         tx.program_id = pid;
+        tx.payer = ctx.accounts.zebec_eoa.key();
+        tx.data_storage = ctx.accounts.data_storage.key();
         tx.accounts = accs.clone();
         tx.did_execute = false;
-        tx.data = data.clone();
+        tx.data = data;
+
+        open_transaction(&mut ctx.accounts.config)?;
 
         let count_stored = ctx.accounts.txn_count.count;
 
@@ -674,25 +1683,25 @@ pub mod solana_project {
 
         //check sender
         let pda_sender_passed: Pubkey = accs[2].pubkey;
-        let sender_stored = ctx.accounts.data_storage.sender.clone();
+        let sender_stored = &ctx.accounts.data_storage.sender;
         require!(
-            sender.to_vec() == sender_stored,
+            sender[..] == sender_stored[..],
             MessengerError::PdaSenderMismatch
         );
 
         //check pdaSender
-        let chain_id_stored = (ctx.accounts.data_storage.from_chain_id).to_string();
-        let chain_id_seed = chain_id_stored.as_bytes();
-        let sender_derived_pubkey: (Pubkey, u8) =
-            Pubkey::find_program_address(&[&sender, &chain_id_seed], ctx.program_id);
+        let sender_derived_pubkey: (Pubkey, u8) = derive_pda_signer(
+            &sender,
+            ctx.accounts.data_storage.from_chain_id,
+            ctx.program_id,
+        );
         require!(
             pda_sender_passed == sender_derived_pubkey.0,
             MessengerError::SenderDerivedKeyMismatch
         );
 
         //check data params passed
-        let data: &[u8] = data.as_slice();
-        let data_slice = &data[8..];
+        let data_slice = &ctx.accounts.transaction.data[8..];
         let decode_data = TokenAmount::try_from_slice(data_slice)?;
         require!(
             decode_data.amount == ctx.accounts.data_storage.amount,
@@ -703,6 +1712,11 @@ pub mod solana_project {
             sender: sender,
             current_count: count_stored
         });
+        emit_lifecycle(
+            ctx.accounts.data_storage.key(),
+            OperationStage::Built,
+            OperationStatus::Succeeded,
+        );
         Ok(())
     }
 
@@ -714,17 +1728,22 @@ pub mod solana_project {
         data: Vec<u8>,
         sender: [u8; 32],
     ) -> Result<()> {
-        require!(
-            !ctx.accounts.txn_status.executed,
-            MessengerError::TransactionAlreadyCreated
-        );
+        check_creatable(&ctx.accounts.txn_status)?;
+        ctx.accounts.txn_status.created = true;
+        check_deadline(&ctx.accounts.data_storage)?;
+        check_operation(&ctx.accounts.data_storage, &[MessageCode::InstantTransfer])?;
+        check_program_id(&ctx.accounts.config, &ctx.accounts.data_storage, pid)?;
 
         //Build Transactions
         let tx = &mut ctx.accounts.transaction;
         tx.program_id = pid;
+        tx.payer = ctx.accounts.zebec_eoa.key();
+        tx.data_storage = ctx.accounts.data_storage.key();
         tx.accounts = accs.clone();
         tx.did_execute = false;
-        tx.data = data.clone();
+        tx.data = data;
+
+        open_transaction(&mut ctx.accounts.config)?;
 
         let count_stored = ctx.accounts.txn_count.count;
 
@@ -737,39 +1756,36 @@ pub mod solana_project {
 
         //check sender
         let pda_sender_passed: Pubkey = accs[2].pubkey;
-        let sender_stored = ctx.accounts.data_storage.sender.clone();
+        let sender_stored = &ctx.accounts.data_storage.sender;
         require!(
-            sender.to_vec() == sender_stored,
+            sender[..] == sender_stored[..],
             MessengerError::PdaSenderMismatch
         );
 
         //check receiver
         let pda_receiver_passed: Pubkey = accs[1].pubkey;
-        let receiver_stored = ctx.accounts.data_storage.receiver.clone();
+        let receiver_stored = &ctx.accounts.data_storage.receiver;
+
+        let chain_seed = chain_id_seed(ctx.accounts.data_storage.from_chain_id);
 
         //check pdaSender
-        let chain_id_stored = (ctx.accounts.data_storage.from_chain_id).to_string();
-        let chain_id_seed = chain_id_stored.as_bytes();
         let sender_derived_pubkey: (Pubkey, u8) =
-            Pubkey::find_program_address(&[&sender, &chain_id_seed], ctx.program_id);
+            derive_pda_signer_with_seed(&sender, chain_seed.as_bytes(), ctx.program_id);
         require!(
             pda_sender_passed == sender_derived_pubkey.0,
             MessengerError::SenderDerivedKeyMismatch
         );
 
         //check pdaReceiver
-        let chain_id_stored = (ctx.accounts.data_storage.from_chain_id).to_string();
-        let chain_id_seed = chain_id_stored.as_bytes();
         let receiver_derived_pubkey: (Pubkey, u8) =
-            Pubkey::find_program_address(&[&receiver_stored, &chain_id_seed], ctx.program_id);
+            derive_pda_signer_with_seed(receiver_stored, chain_seed.as_bytes(), ctx.program_id);
         require!(
             pda_receiver_passed == receiver_derived_pubkey.0,
             MessengerError::ReceiverDerivedKeyMismatch
         );
 
         //check data params passed
-        let data: &[u8] = data.as_slice();
-        let data_slice = &data[8..];
+        let data_slice = &ctx.accounts.transaction.data[8..];
         let decode_data = TokenAmount::try_from_slice(data_slice)?;
         require!(
             decode_data.amount == ctx.accounts.data_storage.amount,
@@ -780,6 +1796,70 @@ pub mod solana_project {
             sender: sender,
             current_count: count_stored,
         });
+        emit_lifecycle(
+            ctx.accounts.data_storage.key(),
+            OperationStage::Built,
+            OperationStatus::Succeeded,
+        );
+        Ok(())
+    }
+
+    // Builds the instruction of a Relay message. The relayer passes the instruction
+    // again, it has to hash to what was stored from the VAA.
+    pub fn create_transaction_relay(
+        ctx: Context<CreateTransaction>,
+        pid: Pubkey,
+        accs: Vec<TransactionAccount>,
+        data: Vec<u8>,
+        sender: [u8; 32],
+    ) -> Result<()> {
+        check_creatable(&ctx.accounts.txn_status)?;
+        ctx.accounts.txn_status.created = true;
+        check_deadline(&ctx.accounts.data_storage)?;
+        check_operation(&ctx.accounts.data_storage, &[MessageCode::Relay])?;
+
+        //check sender
+        require!(
+            sender.to_vec() == ctx.accounts.data_storage.sender,
+            MessengerError::PdaSenderMismatch
+        );
+
+        //check instruction
+        require!(
+            pid == ctx.accounts.data_storage.program_id,
+            MessengerError::UnexpectedProgramId
+        );
+        let instruction = RelayInstruction {
+            program_id: pid,
+            accounts: accs,
+            data: data,
+        };
+        require!(
+            hash_instruction(&instruction.try_to_vec()?)
+                == ctx.accounts.data_storage.instruction_hash,
+            MessengerError::InstructionMismatch
+        );
+
+        //Build Transactions
+        let tx = &mut ctx.accounts.transaction;
+        tx.program_id = instruction.program_id;
+        tx.payer = ctx.accounts.zebec_eoa.key();
+        tx.data_storage = ctx.accounts.data_storage.key();
+        tx.accounts = instruction.accounts;
+        tx.did_execute = false;
+        tx.data = instruction.data;
+
+        open_transaction(&mut ctx.accounts.config)?;
+
+        emit!(RelayCreated {
+            sender: sender,
+            current_count: ctx.accounts.txn_count.count,
+        });
+        emit_lifecycle(
+            ctx.accounts.data_storage.key(),
+            OperationStage::Built,
+            OperationStatus::Succeeded,
+        );
         Ok(())
     }
 
@@ -791,49 +1871,228 @@ pub mod solana_project {
         target_chain: u16,
         fee: u64,
     ) -> Result<()> {
+        check_not_executed(&ctx.accounts.txn_status)?;
         require!(
-            !ctx.accounts.txn_status.executed,
-            MessengerError::TransactionAlreadyExecuted
+            !ctx.accounts.txn_status.staged,
+            MessengerError::TransferAlreadyStaged
         );
-        let transaction_status = &mut ctx.accounts.txn_status;
-        transaction_status.executed = true;
+        check_deadline(&ctx.accounts.data_storage)?;
+        check_operation(&ctx.accounts.data_storage, &[MessageCode::DirectTransfer])?;
 
-        let count_stored = ctx.accounts.txn_count.count;
+        require!(
+            ctx.accounts.data_storage.token_mint == ctx.accounts.mint.key(),
+            MessengerError::DataAccountMismatch
+        );
+
+        let receiver_stored = check_direct_transfer(
+            &ctx.accounts.data_storage,
+            sender,
+            ctx.accounts.pda_signer.key(),
+            ctx.program_id,
+        )?;
+
+        // Leave the bridge CPI to execute_direct_transfer_native.
+        if ctx.accounts.config.two_phase_direct_transfer {
+            let operation_id = ctx.accounts.data_storage.key();
+            stage_direct_transfer(
+                operation_id,
+                &ctx.accounts.config,
+                ctx.accounts.zebec_eoa.key(),
+                &mut ctx.accounts.data_storage,
+                &mut ctx.accounts.txn_status,
+                sender,
+                ctx.accounts.txn_count.count,
+                target_chain,
+                fee,
+            )?;
+            return Ok(());
+        }
+
+        ctx.accounts.txn_status.executed = true;
+        let data_storage = ctx.accounts.data_storage.key();
+
+        emit!(DirectTransferredNative {
+            sender: sender,
+            sender_chain: chain_id.clone(),
+            target_chain: target_chain,
+            receiver: receiver_stored.clone(),
+            current_count: ctx.accounts.txn_count.count,
+            portal_message: ctx.accounts.portal_message.key()
+        });
+
+        transfer_native(ctx, sender, chain_id, target_chain, fee, receiver_stored)?;
+        emit_lifecycle(
+            data_storage,
+            OperationStage::Executed,
+            OperationStatus::Succeeded,
+        );
+        Ok(())
+    }
+
+    // Second phase of a direct transfer staged while two_phase_direct_transfer was set,
+    // bridged with the target chain and fee recorded at staging.
+    pub fn execute_direct_transfer_native(
+        ctx: Context<DirectTransferNative>,
+        sender: [u8; 32],
+        chain_id: Vec<u8>,
+    ) -> Result<()> {
+        require!(
+            ctx.accounts.txn_status.staged,
+            MessengerError::TransferNotStaged
+        );
+        check_not_executed(&ctx.accounts.txn_status)?;
+        check_deadline(&ctx.accounts.data_storage)?;
+        check_operation(&ctx.accounts.data_storage, &[MessageCode::DirectTransfer])?;
 
         require!(
             ctx.accounts.data_storage.token_mint == ctx.accounts.mint.key(),
             MessengerError::DataAccountMismatch
         );
 
-        //check sender
-        let sender_stored = ctx.accounts.data_storage.sender.clone();
+        let receiver_stored = check_direct_transfer(
+            &ctx.accounts.data_storage,
+            sender,
+            ctx.accounts.pda_signer.key(),
+            ctx.program_id,
+        )?;
+        let target_chain = ctx.accounts.data_storage.staged_target_chain;
+        let fee = ctx.accounts.data_storage.staged_fee;
+
+        ctx.accounts.txn_status.executed = true;
+        let data_storage = ctx.accounts.data_storage.key();
+
+        emit!(DirectTransferredNative {
+            sender: sender,
+            sender_chain: chain_id.clone(),
+            target_chain: target_chain,
+            receiver: receiver_stored.clone(),
+            current_count: ctx.accounts.txn_count.count,
+            portal_message: ctx.accounts.portal_message.key()
+        });
+
+        transfer_native(ctx, sender, chain_id, target_chain, fee, receiver_stored)?;
+        emit_lifecycle(
+            data_storage,
+            OperationStage::Executed,
+            OperationStatus::Succeeded,
+        );
+        Ok(())
+    }
+
+    // Direct transfer of native SOL held by the sender's PDA. The lamports are wrapped
+    // into a temporary wSOL account and bridged as a native transfer of the wSOL mint.
+    pub fn transaction_direct_transfer_sol(
+        ctx: Context<DirectTransferSol>,
+        sender: [u8; 32],
+        chain_id: Vec<u8>,
+        target_chain: u16,
+        fee: u64,
+    ) -> Result<()> {
+        check_not_executed(&ctx.accounts.txn_status)?;
         require!(
-            sender.to_vec() == sender_stored,
-            MessengerError::PdaSenderMismatch
+            !ctx.accounts.txn_status.staged,
+            MessengerError::TransferAlreadyStaged
         );
+        check_deadline(&ctx.accounts.data_storage)?;
+        check_operation(&ctx.accounts.data_storage, &[MessageCode::DirectTransfer])?;
 
-        //check receiver
-        let receiver_stored = ctx.accounts.data_storage.receiver.clone();
+        require!(
+            ctx.accounts.data_storage.token_mint == ctx.accounts.mint.key(),
+            MessengerError::DataAccountMismatch
+        );
 
-        //check pdaSender
-        let chain_id_stored = (ctx.accounts.data_storage.from_chain_id).to_string();
-        let chain_id_seed = chain_id_stored.as_bytes();
-        let (sender_derived_pubkey, _): (Pubkey, u8) =
-            Pubkey::find_program_address(&[&sender, &chain_id_seed], ctx.program_id);
+        let receiver_stored = check_direct_transfer(
+            &ctx.accounts.data_storage,
+            sender,
+            ctx.accounts.pda_signer.key(),
+            ctx.program_id,
+        )?;
+
+        // Leave the bridge CPI to execute_direct_transfer_sol.
+        if ctx.accounts.config.two_phase_direct_transfer {
+            let operation_id = ctx.accounts.data_storage.key();
+            stage_direct_transfer(
+                operation_id,
+                &ctx.accounts.config,
+                ctx.accounts.zebec_eoa.key(),
+                &mut ctx.accounts.data_storage,
+                &mut ctx.accounts.txn_status,
+                sender,
+                ctx.accounts.txn_count.count,
+                target_chain,
+                fee,
+            )?;
+            return Ok(());
+        }
+
+        ctx.accounts.txn_status.executed = true;
+        let data_storage = ctx.accounts.data_storage.key();
+
+        emit!(DirectTransferredNative {
+            sender: sender,
+            sender_chain: chain_id.clone(),
+            target_chain: target_chain,
+            receiver: receiver_stored.clone(),
+            current_count: ctx.accounts.txn_count.count,
+            portal_message: ctx.accounts.portal_message.key()
+        });
+
+        transfer_sol(ctx, sender, chain_id, target_chain, fee, receiver_stored)?;
+        emit_lifecycle(
+            data_storage,
+            OperationStage::Executed,
+            OperationStatus::Succeeded,
+        );
+        Ok(())
+    }
+
+    // Second phase of a SOL direct transfer, see execute_direct_transfer_native.
+    pub fn execute_direct_transfer_sol(
+        ctx: Context<DirectTransferSol>,
+        sender: [u8; 32],
+        chain_id: Vec<u8>,
+    ) -> Result<()> {
         require!(
-            ctx.accounts.pda_signer.key() == sender_derived_pubkey,
-            MessengerError::SenderDerivedKeyMismatch
+            ctx.accounts.txn_status.staged,
+            MessengerError::TransferNotStaged
+        );
+        check_not_executed(&ctx.accounts.txn_status)?;
+        check_deadline(&ctx.accounts.data_storage)?;
+        check_operation(&ctx.accounts.data_storage, &[MessageCode::DirectTransfer])?;
+
+        require!(
+            ctx.accounts.data_storage.token_mint == ctx.accounts.mint.key(),
+            MessengerError::DataAccountMismatch
         );
 
+        let receiver_stored = check_direct_transfer(
+            &ctx.accounts.data_storage,
+            sender,
+            ctx.accounts.pda_signer.key(),
+            ctx.program_id,
+        )?;
+        let target_chain = ctx.accounts.data_storage.staged_target_chain;
+        let fee = ctx.accounts.data_storage.staged_fee;
+
+        ctx.accounts.txn_status.executed = true;
+        let data_storage = ctx.accounts.data_storage.key();
+
         emit!(DirectTransferredNative {
             sender: sender,
             sender_chain: chain_id.clone(),
             target_chain: target_chain,
             receiver: receiver_stored.clone(),
-            current_count: count_stored
+            current_count: ctx.accounts.txn_count.count,
+            portal_message: ctx.accounts.portal_message.key()
         });
 
-        transfer_native(ctx, sender, chain_id, target_chain, fee, receiver_stored)
+        transfer_sol(ctx, sender, chain_id, target_chain, fee, receiver_stored)?;
+        emit_lifecycle(
+            data_storage,
+            OperationStage::Executed,
+            OperationStatus::Succeeded,
+        );
+        Ok(())
     }
 
     //create and execute direct transfer wrapped
@@ -841,53 +2100,119 @@ pub mod solana_project {
         ctx: Context<DirectTransferWrapped>,
         sender: [u8; 32],
         sender_chain: Vec<u8>,
-        _token_address: Vec<u8>,
-        _token_chain: u16,
+        token_address: Vec<u8>,
+        token_chain: u16,
         target_chain: u16,
         fee: u64,
     ) -> Result<()> {
+        check_not_executed(&ctx.accounts.txn_status)?;
         require!(
-            !ctx.accounts.txn_status.executed,
-            MessengerError::TransactionAlreadyExecuted
+            !ctx.accounts.txn_status.staged,
+            MessengerError::TransferAlreadyStaged
         );
-        let transaction_status = &mut ctx.accounts.txn_status;
-        transaction_status.executed = true;
+        check_deadline(&ctx.accounts.data_storage)?;
+        check_operation(&ctx.accounts.data_storage, &[MessageCode::DirectTransfer])?;
+        check_wrapped_meta(
+            &ctx.accounts.config,
+            &ctx.accounts.wrapped_meta,
+            &token_address,
+            token_chain,
+        )?;
+
+        let receiver_stored = check_direct_transfer(
+            &ctx.accounts.data_storage,
+            sender,
+            ctx.accounts.pda_signer.key(),
+            ctx.program_id,
+        )?;
+
+        // Leave the bridge CPI to execute_direct_transfer_wrapped.
+        if ctx.accounts.config.two_phase_direct_transfer {
+            let operation_id = ctx.accounts.data_storage.key();
+            stage_direct_transfer(
+                operation_id,
+                &ctx.accounts.config,
+                ctx.accounts.zebec_eoa.key(),
+                &mut ctx.accounts.data_storage,
+                &mut ctx.accounts.txn_status,
+                sender,
+                ctx.accounts.txn_count.count,
+                target_chain,
+                fee,
+            )?;
+            return Ok(());
+        }
 
-        let count_stored = ctx.accounts.txn_count.count;
+        ctx.accounts.txn_status.executed = true;
+        let data_storage = ctx.accounts.data_storage.key();
 
-        //check sender
-        let sender_stored = ctx.accounts.data_storage.sender.clone();
-        require!(
-            sender.to_vec() == sender_stored,
-            MessengerError::PdaSenderMismatch
-        );
+        emit!(DirectTransferredWrapped {
+            sender: sender,
+            sender_chain: sender_chain.clone(),
+            target_chain: target_chain,
+            receiver: receiver_stored.clone(),
+            current_count: ctx.accounts.txn_count.count,
+            portal_message: ctx.accounts.portal_message.key()
+        });
 
-        //check sender
-        let sender_stored = ctx.accounts.data_storage.sender.clone();
-        require!(
-            sender.to_vec() == sender_stored,
-            MessengerError::PdaSenderMismatch
+        transfer_wrapped(
+            ctx,
+            sender.to_vec(),
+            sender_chain,
+            target_chain,
+            fee,
+            receiver_stored,
+        )?;
+        emit_lifecycle(
+            data_storage,
+            OperationStage::Executed,
+            OperationStatus::Succeeded,
         );
+        Ok(())
+    }
 
-        //check receiver
-        let receiver_stored = ctx.accounts.data_storage.receiver.clone();
-
-        //check pdaSender
-        let chain_id_stored = (ctx.accounts.data_storage.from_chain_id).to_string();
-        let chain_id_seed = chain_id_stored.as_bytes();
-        let (sender_derived_pubkey, _): (Pubkey, u8) =
-            Pubkey::find_program_address(&[&sender, &chain_id_seed], &ctx.program_id);
+    // Second phase of a direct transfer staged while two_phase_direct_transfer was set,
+    // bridged with the target chain and fee recorded at staging.
+    pub fn execute_direct_transfer_wrapped(
+        ctx: Context<DirectTransferWrapped>,
+        sender: [u8; 32],
+        sender_chain: Vec<u8>,
+        token_address: Vec<u8>,
+        token_chain: u16,
+    ) -> Result<()> {
         require!(
-            ctx.accounts.pda_signer.key() == sender_derived_pubkey,
-            MessengerError::SenderDerivedKeyMismatch
+            ctx.accounts.txn_status.staged,
+            MessengerError::TransferNotStaged
         );
+        check_not_executed(&ctx.accounts.txn_status)?;
+        check_deadline(&ctx.accounts.data_storage)?;
+        check_operation(&ctx.accounts.data_storage, &[MessageCode::DirectTransfer])?;
+        check_wrapped_meta(
+            &ctx.accounts.config,
+            &ctx.accounts.wrapped_meta,
+            &token_address,
+            token_chain,
+        )?;
+
+        let receiver_stored = check_direct_transfer(
+            &ctx.accounts.data_storage,
+            sender,
+            ctx.accounts.pda_signer.key(),
+            ctx.program_id,
+        )?;
+        let target_chain = ctx.accounts.data_storage.staged_target_chain;
+        let fee = ctx.accounts.data_storage.staged_fee;
+
+        ctx.accounts.txn_status.executed = true;
+        let data_storage = ctx.accounts.data_storage.key();
 
         emit!(DirectTransferredWrapped {
             sender: sender,
             sender_chain: sender_chain.clone(),
             target_chain: target_chain,
             receiver: receiver_stored.clone(),
-            current_count: count_stored,
+            current_count: ctx.accounts.txn_count.count,
+            portal_message: ctx.accounts.portal_message.key()
         });
 
         transfer_wrapped(
@@ -897,18 +2222,23 @@ pub mod solana_project {
             target_chain,
             fee,
             receiver_stored,
-        )
+        )?;
+        emit_lifecycle(
+            data_storage,
+            OperationStage::Executed,
+            OperationStatus::Succeeded,
+        );
+        Ok(())
     }
 
     pub fn execute_transaction(
         ctx: Context<ExecuteTransaction>,
         eth_add: [u8; 32],
         from_chain_id: Vec<u8>,
+        _current_count: u64,
     ) -> Result<()> {
-        require!(
-            !ctx.accounts.txn_status.executed,
-            MessengerError::TransactionAlreadyExecuted
-        );
+        check_not_executed(&ctx.accounts.txn_status)?;
+        check_deadline(&ctx.accounts.data_storage)?;
         let transaction_status = &mut ctx.accounts.txn_status;
         transaction_status.executed = true;
 
@@ -919,238 +2249,1749 @@ pub mod solana_project {
             MessengerError::AlreadyExecuted
         );
 
+        if ctx.accounts.config.enforce_amount_binding {
+            check_amount_binding(&ctx.accounts.data_storage, &ctx.accounts.transaction.data)?;
+        }
+
+        // from_chain_id only has to match the pda_signer seeds, make sure it is also the
+        // chain the operation was stored for, otherwise invoke_signed fails deep in the CPI.
+        if ctx.accounts.config.check_pda_signer {
+            let (pda_signer, _) = derive_pda_signer(
+                &eth_add,
+                ctx.accounts.data_storage.from_chain_id,
+                ctx.program_id,
+            );
+            require!(
+                ctx.accounts.pda_signer.key() == pda_signer,
+                MessengerError::InvalidPdaSigner
+            );
+        }
+
+        check_remaining_accounts(&ctx.accounts.transaction, ctx.remaining_accounts)?;
+
         // Burn the transaction to ensure one time use.
-        ctx.accounts.transaction.did_execute = true;
-        require!(
-            perform_cpi(
-                from_chain_id.clone(),
-                eth_add.clone(),
-                *ctx.accounts.transaction.clone(),
-                ctx.accounts.pda_signer.clone(),
-                ctx.bumps,
-                ctx.remaining_accounts
-            )
-            .is_ok(),
-            MessengerError::InvalidCPI
+        burn_transaction(&mut ctx.accounts.transaction)?;
+        let result = perform_cpi(
+            ctx.accounts.data_storage.from_chain_id,
+            &eth_add,
+            &ctx.accounts.transaction,
+            &ctx.accounts.pda_signer,
+            ctx.bumps,
+            ctx.remaining_accounts,
         );
+        check_cpi(
+            ctx.accounts.transaction.program_id,
+            ctx.accounts.data_storage.key(),
+            result,
+        )?;
+
+        close_transaction(&mut ctx.accounts.config);
 
         emit!(ExecutedTransaction {
             from_chain_id: from_chain_id,
             eth_add: eth_add,
-            transaction: ctx.accounts.transaction.to_account_info().key(),
+            transaction: ctx.accounts.transaction.to_account_info().key()
         });
+        emit_lifecycle(
+            ctx.accounts.data_storage.key(),
+            OperationStage::Executed,
+            OperationStatus::Succeeded,
+        );
         Ok(())
     }
 
-    pub fn transfer_wrapped(
-        ctx: Context<DirectTransferWrapped>,
-        sender: Vec<u8>,
-        sender_chain: Vec<u8>,
-        target_chain: u16,
-        fee: u64,
-        receiver: Vec<u8>,
-    ) -> Result<()> {
-        let amount = ctx.accounts.data_storage.amount;
+    // Discard a built transaction that should no longer run. The account is closed,
+    // and burned first so nothing can execute it within the same transaction.
+    // Cancelling is terminal for the operation: its txn_status stays created, so no
+    // transaction can be built for it again. Only the open slot is released.
+    pub fn cancel_built_transaction(ctx: Context<CancelBuiltTransaction>) -> Result<()> {
+        ctx.accounts.transaction.did_execute = true;
+        close_transaction(&mut ctx.accounts.config);
 
-        //Check EOA
-        require!(
-            ctx.accounts.config.owner == ctx.accounts.zebec_eoa.key(),
-            MessengerError::InvalidCaller
-        );
-        msg!("updated");
-        let bump = ctx.bumps.get("pda_signer").unwrap().to_le_bytes();
+        emit!(TransactionCancelled {
+            transaction: ctx.accounts.transaction.key()
+        });
+        Ok(())
+    }
+}
 
-        let signer_seeds: &[&[&[u8]]] = &[&[&sender, &sender_chain, &bump]];
+// Bridge the wrapped tokens pda_signer holds. Only reachable through the direct
+// transfer instructions, which supply the stored receiver and staged parameters.
+fn transfer_wrapped(
+    ctx: Context<DirectTransferWrapped>,
+    sender: Vec<u8>,
+    _sender_chain: Vec<u8>,
+    target_chain: u16,
+    fee: u64,
+    receiver: Vec<u8>,
+) -> Result<()> {
+    require!(
+        !ctx.accounts.config.bridge_paused,
+        MessengerError::BridgePaused
+    );
+    check_portal_accounts(
+        &ctx.accounts.config,
+        ctx.accounts.portal_emitter.key(),
+        ctx.accounts.portal_sequence.key(),
+    )?;
+
+    let amount = approval_amount(
+        &ctx.accounts.config,
+        ctx.accounts.from.key(),
+        ctx.accounts.data_storage.amount,
+        ctx.accounts.from.amount,
+    )?;
+    check_min_transfer_amount(&ctx.accounts.config, amount)?;
+    check_decimals(&ctx.accounts.data_storage, &ctx.accounts.wrapped_mint)?;
+    check_bridge_sysvars(ctx.accounts.clock.key(), ctx.accounts.rent.key())?;
+    check_transfer_params(
+        &ctx.accounts.config,
+        ctx.accounts.zebec_eoa.key(),
+        target_chain,
+        fee,
+        amount,
+    )?;
+    check_rate_limit(&ctx.accounts.config, &mut ctx.accounts.rate_limit, amount)?;
+
+    let bump = ctx.bumps.get("pda_signer").unwrap().to_le_bytes();
+
+    let chain_seed = chain_id_seed(ctx.accounts.data_storage.from_chain_id);
+    let signer_seeds: &[&[&[u8]]] = &[&[&sender, chain_seed.as_bytes(), &bump]];
+
+    let approve_ctx = CpiContext::new_with_signer(
+        ctx.accounts.token_program.to_account_info(),
+        Approve {
+            to: ctx.accounts.from.to_account_info(),
+            delegate: ctx.accounts.portal_authority_signer.to_account_info(),
+            authority: ctx.accounts.pda_signer.to_account_info(),
+        },
+        signer_seeds,
+    );
 
-        let approve_ctx = CpiContext::new_with_signer(
-            ctx.accounts.token_program.to_account_info(),
-            Approve {
-                to: ctx.accounts.from.to_account_info(),
-                delegate: ctx.accounts.portal_authority_signer.to_account_info(),
-                authority: ctx.accounts.pda_signer.to_account_info(),
+    // Delgate transfer authority to Token Bridge for the tokens
+    approve(approve_ctx, amount)?;
+
+    let target_address: [u8; 32] = receiver.as_slice().try_into().unwrap();
+    // Instruction
+    let transfer_ix = Instruction {
+        program_id: ctx.accounts.config.token_bridge,
+        accounts: vec![
+            AccountMeta::new(ctx.accounts.zebec_eoa.key(), true),
+            AccountMeta::new_readonly(ctx.accounts.portal_config.key(), false),
+            AccountMeta::new(ctx.accounts.from.key(), false),
+            AccountMeta::new_readonly(ctx.accounts.pda_signer.key(), true),
+            AccountMeta::new(ctx.accounts.wrapped_mint.key(), false),
+            AccountMeta::new_readonly(ctx.accounts.wrapped_meta.key(), false),
+            AccountMeta::new_readonly(ctx.accounts.portal_authority_signer.key(), false),
+            AccountMeta::new(ctx.accounts.bridge_config.key(), false),
+            AccountMeta::new(ctx.accounts.portal_message.key(), true),
+            AccountMeta::new_readonly(ctx.accounts.portal_emitter.key(), false),
+            AccountMeta::new(ctx.accounts.portal_sequence.key(), false),
+            AccountMeta::new(ctx.accounts.bridge_fee_collector.key(), false),
+            AccountMeta::new_readonly(ctx.accounts.clock.key(), false),
+            // Dependencies
+            AccountMeta::new_readonly(ctx.accounts.rent.key(), false),
+            AccountMeta::new_readonly(ctx.accounts.system_program.key(), false),
+            // Program
+            AccountMeta::new_readonly(ctx.accounts.core_bridge_program.key(), false),
+            AccountMeta::new_readonly(ctx.accounts.token_program.key(), false),
+        ],
+        data: (
+            crate::portal::Instruction::TransferWrapped,
+            TransferWrappedData {
+                nonce: ctx.accounts.config.nonce,
+                amount,
+                fee,
+                target_address,
+                target_chain,
             },
-            signer_seeds,
-        );
-
-        // Delgate transfer authority to Token Bridge for the tokens
-        approve(approve_ctx, amount)?;
-
-        let target_address: [u8; 32] = receiver.as_slice().try_into().unwrap();
-        // Instruction
-        let transfer_ix = Instruction {
-            program_id: Pubkey::from_str(TOKEN_BRIDGE_ADDRESS).unwrap(),
-            accounts: vec![
-                AccountMeta::new(ctx.accounts.zebec_eoa.key(), true),
-                AccountMeta::new_readonly(ctx.accounts.portal_config.key(), false),
-                AccountMeta::new(ctx.accounts.from.key(), false),
-                AccountMeta::new_readonly(ctx.accounts.pda_signer.key(), true),
-                AccountMeta::new(ctx.accounts.wrapped_mint.key(), false),
-                AccountMeta::new_readonly(ctx.accounts.wrapped_meta.key(), false),
-                AccountMeta::new_readonly(ctx.accounts.portal_authority_signer.key(), false),
-                AccountMeta::new(ctx.accounts.bridge_config.key(), false),
-                AccountMeta::new(ctx.accounts.portal_message.key(), true),
-                AccountMeta::new_readonly(ctx.accounts.portal_emitter.key(), false),
-                AccountMeta::new(ctx.accounts.portal_sequence.key(), false),
-                AccountMeta::new(ctx.accounts.bridge_fee_collector.key(), false),
-                AccountMeta::new_readonly(ctx.accounts.clock.key(), false),
-                // Dependencies
-                AccountMeta::new_readonly(ctx.accounts.rent.key(), false),
-                AccountMeta::new_readonly(ctx.accounts.system_program.key(), false),
-                // Program
-                AccountMeta::new_readonly(ctx.accounts.core_bridge_program.key(), false),
-                AccountMeta::new_readonly(ctx.accounts.token_program.key(), false),
-            ],
-            data: (
-                crate::portal::Instruction::TransferWrapped,
-                TransferWrappedData {
-                    nonce: ctx.accounts.config.nonce,
-                    amount,
-                    fee,
-                    target_address,
-                    target_chain,
-                },
-            )
-                .try_to_vec()?,
-        };
+        )
+            .try_to_vec()?,
+    };
+
+    // Accounts
+    let transfer_accs = vec![
+        ctx.accounts.zebec_eoa.to_account_info(),
+        ctx.accounts.portal_config.to_account_info(),
+        ctx.accounts.from.to_account_info(),
+        ctx.accounts.pda_signer.to_account_info(),
+        ctx.accounts.wrapped_mint.to_account_info(),
+        ctx.accounts.wrapped_meta.to_account_info(),
+        ctx.accounts.portal_authority_signer.to_account_info(),
+        ctx.accounts.bridge_config.to_account_info(),
+        ctx.accounts.portal_message.to_account_info(),
+        ctx.accounts.portal_emitter.to_account_info(),
+        ctx.accounts.portal_sequence.to_account_info(),
+        ctx.accounts.bridge_fee_collector.to_account_info(),
+        ctx.accounts.clock.to_account_info(),
+        // Dependencies
+        ctx.accounts.rent.to_account_info(),
+        ctx.accounts.system_program.to_account_info(),
+        // Program
+        ctx.accounts.core_bridge_program.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
+    ];
+
+    invoke_signed(&transfer_ix, &transfer_accs, signer_seeds)?;
+
+    // A failed bridge transfer fails the instruction and rolls the approval back
+    // with it. On success the bridge may move less than approved (amounts are
+    // truncated to 8 decimals), so drop the delegation that is left.
+    revoke(CpiContext::new_with_signer(
+        ctx.accounts.token_program.to_account_info(),
+        Revoke {
+            source: ctx.accounts.from.to_account_info(),
+            authority: ctx.accounts.pda_signer.to_account_info(),
+        },
+        signer_seeds,
+    ))?;
+
+    let sum = ctx.accounts.config.nonce.checked_add(1);
+    match sum {
+        None => return Err(MessengerError::Overflow.into()),
+        Some(val) => ctx.accounts.config.nonce = val,
+    }
+
+    Ok(())
+}
+
+// Bridge the native tokens pda_signer holds, see transfer_wrapped.
+fn transfer_native(
+    ctx: Context<DirectTransferNative>,
+    sender: [u8; 32],
+    _sender_chain: Vec<u8>,
+    target_chain: u16,
+    fee: u64,
+    receiver: Vec<u8>,
+) -> Result<()> {
+    require!(
+        !ctx.accounts.config.bridge_paused,
+        MessengerError::BridgePaused
+    );
+    check_portal_accounts(
+        &ctx.accounts.config,
+        ctx.accounts.portal_emitter.key(),
+        ctx.accounts.portal_sequence.key(),
+    )?;
+
+    let amount = approval_amount(
+        &ctx.accounts.config,
+        ctx.accounts.from.key(),
+        ctx.accounts.data_storage.amount,
+        ctx.accounts.from.amount,
+    )?;
+    // What the bridge custody receives of a mint charging a transfer fee.
+    let received = post_fee_amount(&ctx.accounts.mint, amount, Clock::get()?.epoch)?;
+    check_min_transfer_amount(&ctx.accounts.config, received)?;
+    check_decimals(&ctx.accounts.data_storage, &ctx.accounts.mint)?;
+    let dust = bridge_dust(
+        amount,
+        Account::<Mint>::try_from(&ctx.accounts.mint)?.decimals,
+    );
+    check_bridge_sysvars(ctx.accounts.clock.key(), ctx.accounts.rent.key())?;
+    check_transfer_params(
+        &ctx.accounts.config,
+        ctx.accounts.zebec_eoa.key(),
+        target_chain,
+        fee,
+        amount,
+    )?;
+    check_rate_limit(&ctx.accounts.config, &mut ctx.accounts.rate_limit, amount)?;
+
+    let bump = ctx.bumps.get("pda_signer").unwrap().to_le_bytes();
+
+    let chain_seed = chain_id_seed(ctx.accounts.data_storage.from_chain_id);
+    let signer_seeds: &[&[&[u8]]] = &[&[&sender, chain_seed.as_bytes(), &bump]];
+
+    let approve_ctx = CpiContext::new_with_signer(
+        ctx.accounts.token_program.to_account_info(),
+        Approve {
+            to: ctx.accounts.from.to_account_info(),
+            delegate: ctx.accounts.portal_authority_signer.to_account_info(),
+            authority: ctx.accounts.pda_signer.to_account_info(),
+        },
+        signer_seeds,
+    );
+
+    // Delgate transfer authority to Token Bridge for the tokens
+    approve(approve_ctx, amount)?;
+
+    let target_address: [u8; 32] = receiver.as_slice().try_into().unwrap();
+    // Instruction
+    let transfer_ix = Instruction {
+        program_id: ctx.accounts.config.token_bridge,
+        accounts: vec![
+            AccountMeta::new(ctx.accounts.zebec_eoa.key(), true),
+            AccountMeta::new_readonly(ctx.accounts.portal_config.key(), false),
+            AccountMeta::new(ctx.accounts.from.key(), false),
+            AccountMeta::new(ctx.accounts.mint.key(), false),
+            AccountMeta::new(ctx.accounts.portal_custody.key(), false),
+            AccountMeta::new_readonly(ctx.accounts.portal_authority_signer.key(), false),
+            AccountMeta::new_readonly(ctx.accounts.portal_custody_signer.key(), false),
+            AccountMeta::new(ctx.accounts.bridge_config.key(), false),
+            AccountMeta::new(ctx.accounts.portal_message.key(), true),
+            AccountMeta::new_readonly(ctx.accounts.portal_emitter.key(), false),
+            AccountMeta::new(ctx.accounts.portal_sequence.key(), false),
+            AccountMeta::new(ctx.accounts.bridge_fee_collector.key(), false),
+            AccountMeta::new_readonly(ctx.accounts.clock.key(), false),
+            // Dependencies
+            AccountMeta::new_readonly(ctx.accounts.rent.key(), false),
+            AccountMeta::new_readonly(ctx.accounts.system_program.key(), false),
+            // Program
+            AccountMeta::new_readonly(ctx.accounts.core_bridge_program.key(), false),
+            AccountMeta::new_readonly(ctx.accounts.token_program.key(), false),
+        ],
+        data: (
+            crate::portal::Instruction::TransferNative,
+            TransferNativeData {
+                nonce: ctx.accounts.config.nonce,
+                amount,
+                fee,
+                target_address,
+                target_chain,
+            },
+        )
+            .try_to_vec()?,
+    };
+
+    // Accounts
+    let transfer_accs = vec![
+        ctx.accounts.zebec_eoa.to_account_info(),
+        ctx.accounts.portal_config.to_account_info(),
+        ctx.accounts.from.to_account_info(),
+        ctx.accounts.mint.to_account_info(),
+        ctx.accounts.portal_custody.to_account_info(),
+        ctx.accounts.portal_authority_signer.to_account_info(),
+        ctx.accounts.portal_custody_signer.to_account_info(),
+        ctx.accounts.bridge_config.to_account_info(),
+        ctx.accounts.portal_message.to_account_info(),
+        ctx.accounts.portal_emitter.to_account_info(),
+        ctx.accounts.portal_sequence.to_account_info(),
+        ctx.accounts.bridge_fee_collector.to_account_info(),
+        ctx.accounts.clock.to_account_info(),
+        // Dependencies
+        ctx.accounts.rent.to_account_info(),
+        ctx.accounts.system_program.to_account_info(),
+        // Program
+        ctx.accounts.core_bridge_program.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
+    ];
+
+    invoke_signed(&transfer_ix, &transfer_accs, signer_seeds)?;
+
+    // A failed bridge transfer fails the instruction and rolls the approval back
+    // with it. On success the bridge may move less than approved (amounts are
+    // truncated to 8 decimals), so drop the delegation that is left.
+    revoke(CpiContext::new_with_signer(
+        ctx.accounts.token_program.to_account_info(),
+        Revoke {
+            source: ctx.accounts.from.to_account_info(),
+            authority: ctx.accounts.pda_signer.to_account_info(),
+        },
+        signer_seeds,
+    ))?;
+
+    if let Some(event) = dust_truncated(ctx.accounts.mint.key(), dust) {
+        emit!(event);
+    }
+
+    let sum = ctx.accounts.config.nonce.checked_add(1);
+    match sum {
+        None => return Err(MessengerError::Overflow.into()),
+        Some(val) => ctx.accounts.config.nonce = val,
+    }
+
+    Ok(())
+}
 
-        // Accounts
-        let transfer_accs = vec![
+// transfer_native for lamports held by pda_signer: wrap them into the temporary wSOL
+// account, bridge that, then close the account so zebec_eoa gets its rent back. What
+// the bridge truncates stays with pda_signer.
+fn transfer_sol(
+    ctx: Context<DirectTransferSol>,
+    sender: [u8; 32],
+    _sender_chain: Vec<u8>,
+    target_chain: u16,
+    fee: u64,
+    receiver: Vec<u8>,
+) -> Result<()> {
+    require!(
+        !ctx.accounts.config.bridge_paused,
+        MessengerError::BridgePaused
+    );
+    check_portal_accounts(
+        &ctx.accounts.config,
+        ctx.accounts.portal_emitter.key(),
+        ctx.accounts.portal_sequence.key(),
+    )?;
+
+    let amount = approval_amount(
+        &ctx.accounts.config,
+        ctx.accounts.pda_signer.key(),
+        ctx.accounts.data_storage.amount,
+        ctx.accounts.pda_signer.lamports(),
+    )?;
+    check_min_transfer_amount(&ctx.accounts.config, amount)?;
+    check_decimals(
+        &ctx.accounts.data_storage,
+        &ctx.accounts.mint.to_account_info(),
+    )?;
+    check_bridge_sysvars(ctx.accounts.clock.key(), ctx.accounts.rent.key())?;
+    check_transfer_params(
+        &ctx.accounts.config,
+        ctx.accounts.zebec_eoa.key(),
+        target_chain,
+        fee,
+        amount,
+    )?;
+    check_rate_limit(&ctx.accounts.config, &mut ctx.accounts.rate_limit, amount)?;
+
+    let bump = ctx.bumps.get("pda_signer").unwrap().to_le_bytes();
+
+    let chain_seed = chain_id_seed(ctx.accounts.data_storage.from_chain_id);
+    let signer_seeds: &[&[&[u8]]] = &[&[&sender, chain_seed.as_bytes(), &bump]];
+
+    let pda_signer = ctx.accounts.pda_signer.key();
+    let wsol_bump = ctx.bumps.get("from").unwrap().to_le_bytes();
+    let wsol_seeds: &[&[&[u8]]] = &[&[b"wsol", pda_signer.as_ref(), &wsol_bump]];
+    let space =
+        <anchor_spl::token::spl_token::state::Account as solana_program::program_pack::Pack>::LEN;
+    invoke_signed(
+        &solana_program::system_instruction::create_account(
+            ctx.accounts.zebec_eoa.key,
+            ctx.accounts.from.key,
+            Rent::get()?.minimum_balance(space),
+            space as u64,
+            ctx.accounts.token_program.key,
+        ),
+        &[
             ctx.accounts.zebec_eoa.to_account_info(),
-            ctx.accounts.portal_config.to_account_info(),
             ctx.accounts.from.to_account_info(),
+            ctx.accounts.system_program.to_account_info(),
+        ],
+        wsol_seeds,
+    )?;
+    initialize_account(CpiContext::new(
+        ctx.accounts.token_program.to_account_info(),
+        InitializeAccount {
+            account: ctx.accounts.from.to_account_info(),
+            mint: ctx.accounts.mint.to_account_info(),
+            authority: ctx.accounts.pda_signer.to_account_info(),
+            rent: ctx.accounts.rent.to_account_info(),
+        },
+    ))?;
+
+    // Wrap
+    invoke_signed(
+        &solana_program::system_instruction::transfer(
+            ctx.accounts.pda_signer.key,
+            &ctx.accounts.from.key(),
+            amount,
+        ),
+        &[
             ctx.accounts.pda_signer.to_account_info(),
-            ctx.accounts.wrapped_mint.to_account_info(),
-            ctx.accounts.wrapped_meta.to_account_info(),
-            ctx.accounts.portal_authority_signer.to_account_info(),
-            ctx.accounts.bridge_config.to_account_info(),
-            ctx.accounts.portal_message.to_account_info(),
-            ctx.accounts.portal_emitter.to_account_info(),
-            ctx.accounts.portal_sequence.to_account_info(),
-            ctx.accounts.bridge_fee_collector.to_account_info(),
-            ctx.accounts.clock.to_account_info(),
-            // Dependencies
-            ctx.accounts.rent.to_account_info(),
+            ctx.accounts.from.to_account_info(),
             ctx.accounts.system_program.to_account_info(),
-            // Program
-            ctx.accounts.core_bridge_program.to_account_info(),
+        ],
+        signer_seeds,
+    )?;
+    solana_program::program::invoke(
+        &anchor_spl::token::spl_token::instruction::sync_native(
+            ctx.accounts.token_program.key,
+            &ctx.accounts.from.key(),
+        )?,
+        &[
+            ctx.accounts.from.to_account_info(),
             ctx.accounts.token_program.to_account_info(),
-        ];
+        ],
+    )?;
+
+    let approve_ctx = CpiContext::new_with_signer(
+        ctx.accounts.token_program.to_account_info(),
+        Approve {
+            to: ctx.accounts.from.to_account_info(),
+            delegate: ctx.accounts.portal_authority_signer.to_account_info(),
+            authority: ctx.accounts.pda_signer.to_account_info(),
+        },
+        signer_seeds,
+    );
+
+    // Delgate transfer authority to Token Bridge for the tokens
+    approve(approve_ctx, amount)?;
+
+    let target_address: [u8; 32] = receiver.as_slice().try_into().unwrap();
+    // Instruction
+    let transfer_ix = Instruction {
+        program_id: ctx.accounts.config.token_bridge,
+        accounts: vec![
+            AccountMeta::new(ctx.accounts.zebec_eoa.key(), true),
+            AccountMeta::new_readonly(ctx.accounts.portal_config.key(), false),
+            AccountMeta::new(ctx.accounts.from.key(), false),
+            AccountMeta::new(ctx.accounts.mint.key(), false),
+            AccountMeta::new(ctx.accounts.portal_custody.key(), false),
+            AccountMeta::new_readonly(ctx.accounts.portal_authority_signer.key(), false),
+            AccountMeta::new_readonly(ctx.accounts.portal_custody_signer.key(), false),
+            AccountMeta::new(ctx.accounts.bridge_config.key(), false),
+            AccountMeta::new(ctx.accounts.portal_message.key(), true),
+            AccountMeta::new_readonly(ctx.accounts.portal_emitter.key(), false),
+            AccountMeta::new(ctx.accounts.portal_sequence.key(), false),
+            AccountMeta::new(ctx.accounts.bridge_fee_collector.key(), false),
+            AccountMeta::new_readonly(ctx.accounts.clock.key(), false),
+            // Dependencies
+            AccountMeta::new_readonly(ctx.accounts.rent.key(), false),
+            AccountMeta::new_readonly(ctx.accounts.system_program.key(), false),
+            // Program
+            AccountMeta::new_readonly(ctx.accounts.core_bridge_program.key(), false),
+            AccountMeta::new_readonly(ctx.accounts.token_program.key(), false),
+        ],
+        data: (
+            crate::portal::Instruction::TransferNative,
+            TransferNativeData {
+                nonce: ctx.accounts.config.nonce,
+                amount,
+                fee,
+                target_address,
+                target_chain,
+            },
+        )
+            .try_to_vec()?,
+    };
+
+    // Accounts
+    let transfer_accs = vec![
+        ctx.accounts.zebec_eoa.to_account_info(),
+        ctx.accounts.portal_config.to_account_info(),
+        ctx.accounts.from.to_account_info(),
+        ctx.accounts.mint.to_account_info(),
+        ctx.accounts.portal_custody.to_account_info(),
+        ctx.accounts.portal_authority_signer.to_account_info(),
+        ctx.accounts.portal_custody_signer.to_account_info(),
+        ctx.accounts.bridge_config.to_account_info(),
+        ctx.accounts.portal_message.to_account_info(),
+        ctx.accounts.portal_emitter.to_account_info(),
+        ctx.accounts.portal_sequence.to_account_info(),
+        ctx.accounts.bridge_fee_collector.to_account_info(),
+        ctx.accounts.clock.to_account_info(),
+        // Dependencies
+        ctx.accounts.rent.to_account_info(),
+        ctx.accounts.system_program.to_account_info(),
+        // Program
+        ctx.accounts.core_bridge_program.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
+    ];
+
+    invoke_signed(&transfer_ix, &transfer_accs, signer_seeds)?;
+
+    // wSOL has 9 decimals, the bridge leaves what it truncates in the account.
+    let dust = TokenAccount::try_deserialize(&mut &ctx.accounts.from.data.borrow()[..])?.amount;
+
+    // Unwrap, returning the rent of the temporary account.
+    close_account(CpiContext::new_with_signer(
+        ctx.accounts.token_program.to_account_info(),
+        CloseAccount {
+            account: ctx.accounts.from.to_account_info(),
+            destination: ctx.accounts.zebec_eoa.to_account_info(),
+            authority: ctx.accounts.pda_signer.to_account_info(),
+        },
+        signer_seeds,
+    ))?;
+
+    // The dust closed into zebec_eoa with the rent belongs to the sender.
+    if dust > 0 {
+        solana_program::program::invoke(
+            &solana_program::system_instruction::transfer(
+                ctx.accounts.zebec_eoa.key,
+                ctx.accounts.pda_signer.key,
+                dust,
+            ),
+            &[
+                ctx.accounts.zebec_eoa.to_account_info(),
+                ctx.accounts.pda_signer.to_account_info(),
+                ctx.accounts.system_program.to_account_info(),
+            ],
+        )?;
+    }
+    if let Some(event) = dust_truncated(ctx.accounts.mint.key(), dust) {
+        emit!(event);
+    }
+
+    let sum = ctx.accounts.config.nonce.checked_add(1);
+    match sum {
+        None => return Err(MessengerError::Overflow.into()),
+        Some(val) => ctx.accounts.config.nonce = val,
+    }
+
+    Ok(())
+}
+
+// The wrapped mint is derived from the token address and chain, check that the meta
+// the bridge reads describes that same asset.
+fn check_wrapped_meta(
+    config: &Config,
+    wrapped_meta: &AccountInfo,
+    token_address: &[u8],
+    token_chain: u16,
+) -> Result<()> {
+    require!(
+        wrapped_meta.owner == &config.token_bridge,
+        MessengerError::WrappedMetaMismatch
+    );
+    let meta = WrappedMeta::deserialize(&mut &wrapped_meta.data.borrow()[..])
+        .map_err(|_| MessengerError::WrappedMetaMismatch)?;
+    require!(
+        meta.chain == token_chain && meta.token_address.as_slice() == token_address,
+        MessengerError::WrappedMetaMismatch
+    );
+    Ok(())
+}
+
+// Bridge transfers are pushed by the owner or the relayer authority.
+fn check_caller(config: &Config, caller: Pubkey) -> Result<()> {
+    let is_relayer =
+        config.relayer_authority != Pubkey::default() && caller == config.relayer_authority;
+    require!(
+        caller == config.owner || is_relayer,
+        MessengerError::InvalidCaller
+    );
+    Ok(())
+}
+
+// A stream withdrawal may also be built by the receiver itself, when the stored 32
+// byte receiver is the Solana wallet signing, so liveness of a user's own withdrawal
+// doesn't hang on the relayer.
+fn check_withdraw_caller(
+    config: &Config,
+    caller: Pubkey,
+    transaction_data: &TransactionData,
+) -> Result<()> {
+    if caller.as_ref() == transaction_data.receiver.as_slice() {
+        return Ok(());
+    }
+    check_caller(config, caller)
+}
 
-        invoke_signed(&transfer_ix, &transfer_accs, signer_seeds)?;
+// Circuit breaker on outbound transfers: at most config.max_amount_per_window may
+// leave through the bridge per window_seconds.
+fn check_rate_limit(config: &Config, rate_limit: &mut RateLimit, amount: u64) -> Result<()> {
+    if config.max_amount_per_window == 0 {
+        return Ok(());
+    }
 
-        let sum = ctx.accounts.config.nonce.checked_add(1);
-        match sum {
-            None => return Err(MessengerError::Overflow.into()),
-            Some(val) => ctx.accounts.config.nonce = val,
+    let now = Clock::get()?.unix_timestamp;
+    if now.saturating_sub(rate_limit.window_start) >= config.window_seconds as i64 {
+        rate_limit.window_start = now;
+        rate_limit.amount = 0;
+    }
+
+    let total = checked_add(rate_limit.amount, amount)?;
+    require!(
+        total <= config.max_amount_per_window,
+        MessengerError::RateLimitExceeded
+    );
+    rate_limit.amount = total;
+    Ok(())
+}
+
+// Fold a stored message into the totals of day, under its mint. Saturating so
+// analytics can never block a message.
+fn record_daily_stats(stats: &mut DailyStats, day: u64, token_mint: Pubkey, amount: u64) {
+    // The account is seeded by the day, a fresh one starts zeroed.
+    stats.day = day;
+    stats.count = stats.count.saturating_add(1);
+
+    match stats
+        .mints
+        .iter()
+        .position(|entry| entry.mint == token_mint)
+    {
+        Some(index) => {
+            let entry = &mut stats.mints[index];
+            entry.count = entry.count.saturating_add(1);
+            entry.volume = entry.volume.saturating_add(amount);
         }
+        None if stats.mints.len() < MAX_DAILY_STATS_MINTS => stats.mints.push(MintVolume {
+            mint: token_mint,
+            count: 1,
+            volume: amount,
+        }),
+        None => {}
+    }
+}
 
-        Ok(())
+// The first message stored on a later day than config.last_stats_day emits the
+// totals of that day, previous being its DailyStats.
+fn roll_over_daily_stats(config: &mut Config, previous: &AccountInfo, day: u64) -> Result<()> {
+    if config.last_stats_day == day {
+        return Ok(());
+    }
+    if let Some(rollup) = daily_rollup(previous)? {
+        emit!(rollup);
     }
+    config.last_stats_day = day;
+    Ok(())
+}
 
-    //transfer
-    pub fn transfer_native(
-        ctx: Context<DirectTransferNative>,
-        sender: [u8; 32],
-        sender_chain: Vec<u8>,
-        target_chain: u16,
-        fee: u64,
-        receiver: Vec<u8>,
-    ) -> Result<()> {
-        let amount = ctx.accounts.data_storage.amount;
-        //Check EOA
+// None when no message was stored that day, or ever.
+fn daily_rollup(stats: &AccountInfo) -> Result<Option<DailyRollup>> {
+    if stats.data_is_empty() {
+        return Ok(None);
+    }
+    let stats = Account::<DailyStats>::try_from(stats)?;
+    Ok(Some(DailyRollup {
+        day: stats.day,
+        count: stats.count,
+        volume: stats
+            .mints
+            .iter()
+            .fold(0u64, |volume, entry| volume.saturating_add(entry.volume)),
+    }))
+}
+
+// Moving the count down is fine as long as the operation stored next doesn't exist
+// yet, otherwise store_msg could never create it. Returns the previous count.
+fn reset_count(txn_count: &mut Count, value: u64, next_data_storage: &AccountInfo) -> Result<u64> {
+    require!(
+        next_data_storage.data_is_empty(),
+        MessengerError::CountMismatch
+    );
+    let previous = txn_count.count;
+    txn_count.count = value;
+    Ok(previous)
+}
+
+// A transaction is built once per operation, and never once the operation ran.
+fn check_creatable(txn_status: &TransactionStatus) -> Result<()> {
+    require!(!txn_status.created, MessengerError::AlreadyCreated);
+    check_not_executed(txn_status)
+}
+
+fn check_not_executed(txn_status: &TransactionStatus) -> Result<()> {
+    require!(!txn_status.executed, MessengerError::AlreadyExecuted);
+    Ok(())
+}
+
+// Days since the unix epoch, the seed of the DailyStats account of today.
+pub fn current_day() -> Result<u64> {
+    Ok((Clock::get()?.unix_timestamp / SECONDS_PER_DAY) as u64)
+}
+
+// data_storage is shared by every operation of a sender, make sure the one being
+// built is the one that was last stored.
+fn check_operation(transaction_data: &TransactionData, codes: &[MessageCode]) -> Result<()> {
+    require!(
+        codes
+            .iter()
+            .any(|code| transaction_data.msg_type == *code as u8),
+        MessengerError::OperationMismatch
+    );
+    Ok(())
+}
+
+// pid comes from the client; when enabled it has to match the program stored for
+// the operation at store time.
+fn check_program_id(
+    config: &Config,
+    transaction_data: &TransactionData,
+    pid: Pubkey,
+) -> Result<()> {
+    require!(
+        !config.check_program_id || pid == transaction_data.program_id,
+        MessengerError::UnexpectedProgramId
+    );
+    Ok(())
+}
+
+// The PDA signing for a foreign wallet is seeded with the 32 byte wallet and the
+// Wormhole chain id it sends from as a decimal string, e.g. b"4" for BSC. Derivation
+// checks and invoke_signed both go through these so the two can't drift apart.
+fn chain_id_seed(chain_id: u64) -> String {
+    chain_id.to_string()
+}
+
+fn derive_pda_signer(wallet: &[u8], chain_id: u64, program_id: &Pubkey) -> (Pubkey, u8) {
+    derive_pda_signer_with_seed(wallet, chain_id_seed(chain_id).as_bytes(), program_id)
+}
+
+// For callers deriving both PDAs of an operation, so the seed is formatted once.
+fn derive_pda_signer_with_seed(
+    wallet: &[u8],
+    chain_seed: &[u8],
+    program_id: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[wallet, chain_seed], program_id)
+}
+
+// Checks shared by the direct transfer instructions, returns the stored receiver.
+fn check_direct_transfer(
+    data_storage: &TransactionData,
+    sender: [u8; 32],
+    pda_signer: Pubkey,
+    program_id: &Pubkey,
+) -> Result<Vec<u8>> {
+    //check sender
+    require!(
+        sender.to_vec() == data_storage.sender,
+        MessengerError::PdaSenderMismatch
+    );
+
+    //check pdaSender
+    let (sender_derived_pubkey, _): (Pubkey, u8) =
+        derive_pda_signer(&sender, data_storage.from_chain_id, program_id);
+    require!(
+        pda_signer == sender_derived_pubkey,
+        MessengerError::SenderDerivedKeyMismatch
+    );
+
+    Ok(data_storage.receiver.clone())
+}
+
+// First phase of a two phase direct transfer. The target chain and fee are kept on
+// the operation so the second phase bridges exactly what was staged; the receiver
+// is the one stored from the VAA. They get the same checks as when bridged in one go.
+#[allow(clippy::too_many_arguments)]
+fn stage_direct_transfer(
+    operation_id: Pubkey,
+    config: &Config,
+    caller: Pubkey,
+    data_storage: &mut TransactionData,
+    txn_status: &mut TransactionStatus,
+    sender: [u8; 32],
+    current_count: u64,
+    target_chain: u16,
+    fee: u64,
+) -> Result<()> {
+    check_transfer_params(config, caller, target_chain, fee, data_storage.amount)?;
+    data_storage.staged_target_chain = target_chain;
+    data_storage.staged_fee = fee;
+    txn_status.staged = true;
+    emit!(DirectTransferStaged {
+        sender: sender,
+        current_count: current_count
+    });
+    emit_lifecycle(
+        operation_id,
+        OperationStage::Built,
+        OperationStatus::Succeeded,
+    );
+    Ok(())
+}
+
+// Only the owner or relayer picks where a direct transfer goes and what the bridge
+// takes of it, the fee coming out of the transferred amount and leaving some of it
+// for the receiver.
+fn check_transfer_params(
+    config: &Config,
+    caller: Pubkey,
+    target_chain: u16,
+    fee: u64,
+    amount: u64,
+) -> Result<()> {
+    check_caller(config, caller)?;
+    check_target_chain(config, target_chain)?;
+    let delivered = checked_sub(amount, fee)?;
+    require!(delivered > 0, MessengerError::FeeConsumesAmount);
+    Ok(())
+}
+
+// The bridge reads the time and rent from whatever accounts it is handed, so a
+// fake clock or rent account would let a caller skew the posted message.
+fn check_bridge_sysvars(clock: Pubkey, rent: Pubkey) -> Result<()> {
+    require!(
+        clock == anchor_lang::solana_program::sysvar::clock::ID,
+        MessengerError::InvalidClockAccount
+    );
+    require!(
+        rent == anchor_lang::solana_program::sysvar::rent::ID,
+        MessengerError::InvalidRentAccount
+    );
+    Ok(())
+}
+
+// Emitters are stored in the 32 byte form VAAs carry, as hex. EVM addresses (0x and
+// 40 digits) are left-padded into it, other chains pass the 32 byte form directly.
+fn normalize_emitter_addr(emitter_addr: &str) -> Result<String> {
+    let digits = emitter_addr.strip_prefix("0x").unwrap_or(emitter_addr);
+    let is_evm = emitter_addr.starts_with("0x") && emitter_addr.len() == EVM_CHAIN_ADDRESS_LENGTH;
+    require!(
+        (is_evm || digits.len() == WORMHOLE_ADDRESS_LENGTH)
+            && digits.chars().all(|c| c.is_ascii_hexdigit()),
+        MessengerError::InvalidEmitterAddress
+    );
+    Ok(format!(
+        "{:0>width$}",
+        digits.to_ascii_lowercase(),
+        width = WORMHOLE_ADDRESS_LENGTH
+    ))
+}
+
+fn decode_emitter_addr(emitter_addr: &str) -> Result<[u8; 32]> {
+    let mut emitter_bytes = [0u8; 32];
+    hex::decode_to_slice(emitter_addr, &mut emitter_bytes)
+        .map_err(|_| MessengerError::InvalidEmitterAddress)?;
+    Ok(emitter_bytes)
+}
+
+// Reserve a slot for a newly built transaction, bounded by config.max_open_transactions.
+fn open_transaction(config: &mut Config) -> Result<()> {
+    require!(
+        config.max_open_transactions == 0
+            || config.open_transactions < config.max_open_transactions,
+        MessengerError::TooManyOpenTransactions
+    );
+
+    let sum = config.open_transactions.checked_add(1);
+    match sum {
+        None => return Err(MessengerError::Overflow.into()),
+        Some(val) => config.open_transactions = val,
+    }
+    Ok(())
+}
+
+// Release the slot once the transaction is executed. Saturating so transactions
+// built before the counter existed can't underflow it.
+fn close_transaction(config: &mut Config) {
+    config.open_transactions = config.open_transactions.saturating_sub(1);
+}
+
+// Amounts in payloads are base units of the mint. An amount above the mint's total
+// supply almost always means the source chain scaled it with the wrong decimals, so
+// when enabled we surface it as a warning event. The mint is looked up in
+// remaining_accounts; if the caller didn't pass it there is nothing to compare against.
+fn check_amount_plausibility(env: &MsgEnv, token_mint: Pubkey, amount: u64) -> Result<()> {
+    if !env.config.check_amount_plausibility {
+        return Ok(());
+    }
+
+    if let Some(mint_info) = env
+        .remaining_accounts
+        .iter()
+        .find(|acc| acc.key() == token_mint)
+    {
+        let mint = Account::<Mint>::try_from(mint_info)?;
+        if amount > mint.supply {
+            emit!(SuspiciousAmount {
+                token_mint: token_mint,
+                amount: amount,
+                supply: mint.supply
+            });
+        }
+    }
+    Ok(())
+}
+
+// Accounts and split of a cancel transaction, whoever of the two parties signed it.
+fn check_cancel(
+    data_storage: &TransactionData,
+    accs: &[TransactionAccount],
+    data: &[u8],
+    program_id: &Pubkey,
+) -> Result<()> {
+    //check Mint passed
+    require!(
+        accs[12].pubkey == data_storage.token_mint,
+        MessengerError::MintKeyMismatch
+    );
+
+    //check data account
+    require!(
+        accs[6].pubkey == data_storage.data_account,
+        MessengerError::DataAccountMismatch
+    );
+
+    let chain_seed = chain_id_seed(data_storage.from_chain_id);
+
+    //check pdaSender
+    let sender_derived_pubkey: (Pubkey, u8) =
+        derive_pda_signer_with_seed(&data_storage.sender, chain_seed.as_bytes(), program_id);
+    require!(
+        accs[2].pubkey == sender_derived_pubkey.0,
+        MessengerError::SenderDerivedKeyMismatch
+    );
+
+    //check pdaReceiver
+    let receiver_derived_pubkey: (Pubkey, u8) =
+        derive_pda_signer_with_seed(&data_storage.receiver, chain_seed.as_bytes(), program_id);
+    require!(
+        accs[1].pubkey == receiver_derived_pubkey.0,
+        MessengerError::ReceiverDerivedKeyMismatch
+    );
+
+    //check settlement split, only when the payload carried one
+    if data_storage.refund_amount != 0 || data_storage.amount != 0 {
+        let decode_data = CancelSplit::try_from_slice(&data[8..])?;
         require!(
-            ctx.accounts.config.owner == ctx.accounts.zebec_eoa.key(),
-            MessengerError::InvalidCaller
+            decode_data.sender_refund == data_storage.refund_amount
+                && decode_data.receiver_settlement == data_storage.amount,
+            MessengerError::AmountMismatch
         );
+    }
+    Ok(())
+}
+
+// can_update and can_cancel of the stream an update or cancel targets.
+fn stream_permissions(
+    transaction_data: &TransactionData,
+    remaining_accounts: &[AccountInfo],
+) -> Result<(bool, bool)> {
+    let stream = find_stream(transaction_data, remaining_accounts)?;
+    Ok((stream.can_update, stream.can_cancel))
+}
+
+// The DataStorage the stream was created from is passed in remaining_accounts and
+// matched on the Zebec data account and the sender.
+fn find_stream<'info>(
+    transaction_data: &TransactionData,
+    remaining_accounts: &[AccountInfo<'info>],
+) -> Result<Account<'info, TransactionData>> {
+    for acc in remaining_accounts
+        .iter()
+        .filter(|acc| acc.owner == &crate::ID)
+    {
+        if let Ok(stream) = Account::<TransactionData>::try_from(acc) {
+            let is_stream = stream.msg_type == MessageCode::Stream as u8
+                || stream.msg_type == MessageCode::StreamWithCliff as u8;
+            if is_stream
+                && stream.data_account == transaction_data.data_account
+                && stream.sender == transaction_data.sender
+            {
+                return Ok(stream);
+            }
+        }
+    }
+    Err(MessengerError::StreamNotFound.into())
+}
+
+// Until set_portal_accounts ran, the seeds constraints of the context are all there is.
+fn check_portal_accounts(
+    config: &Config,
+    portal_emitter: Pubkey,
+    portal_sequence: Pubkey,
+) -> Result<()> {
+    if config.portal_emitter == Pubkey::default() {
+        return Ok(());
+    }
+    require!(
+        portal_emitter == config.portal_emitter && portal_sequence == config.portal_sequence,
+        MessengerError::PortalAccountMismatch
+    );
+    Ok(())
+}
+
+// Funds sent to a chain the bridge doesn't serve can't be recovered.
+fn check_target_chain(config: &Config, target_chain: u16) -> Result<()> {
+    require!(
+        target_chain != 0
+            && target_chain != CHAIN_ID_SOLANA
+            && (config.target_chains.is_empty() || config.target_chains.contains(&target_chain)),
+        MessengerError::UnsupportedTargetChain
+    );
+    Ok(())
+}
+
+fn check_mint_allowed(config: &Config, token_mint: Pubkey) -> Result<()> {
+    require!(
+        config.allowed_mints.is_empty() || config.allowed_mints.contains(&token_mint),
+        MessengerError::MintNotAllowed
+    );
+    Ok(())
+}
+
+// The amount was validated against DataStorage when the transaction was built;
+// make sure DataStorage hasn't moved away from it since. Only the operations whose
+// Zebec instruction carries an amount are checked.
+fn check_amount_binding(transaction_data: &TransactionData, data: &[u8]) -> Result<()> {
+    let mut data_slice = data.get(8..).ok_or(MessengerError::InvalidPayload)?;
+    let amount = match MessageCode::try_from(transaction_data.msg_type as u64) {
+        Ok(MessageCode::Stream) | Ok(MessageCode::StreamWithCliff) => {
+            Stream::deserialize(&mut data_slice)?.amount
+        }
+        Ok(MessageCode::Withdraw) | Ok(MessageCode::InstantTransfer) => {
+            TokenAmount::deserialize(&mut data_slice)?.amount
+        }
+        Ok(MessageCode::WithdrawStream) if transaction_data.amount != 0 => {
+            TokenAmount::deserialize(&mut data_slice)?.amount
+        }
+        _ => return Ok(()),
+    };
+    require!(
+        amount == transaction_data.amount,
+        MessengerError::AmountChanged
+    );
+    Ok(())
+}
+
+// Rescale an amount from the emitter chain's decimals to the decimals of the mint,
+// which then has to be passed in remaining_accounts. Dropping a non-zero remainder
+// loses value, so it is rejected unless allow_precision_loss is set.
+fn normalize_amount(env: &MsgEnv, token_mint: Pubkey, amount: u64) -> Result<u64> {
+    if env.source_decimals == 0 {
+        return Ok(amount);
+    }
+
+    let mint_info = env
+        .remaining_accounts
+        .iter()
+        .find(|acc| acc.key() == token_mint)
+        .ok_or(MessengerError::MissingMintAccount)?;
+    let mint_decimals = Account::<Mint>::try_from(mint_info)?.decimals;
+
+    if env.source_decimals <= mint_decimals {
+        let factor = checked_pow10(mint_decimals - env.source_decimals)?;
+        return checked_mul(amount, factor);
+    }
+
+    let factor = checked_pow10(env.source_decimals - mint_decimals)?;
+    let truncated = amount % factor;
+    require!(
+        env.config.allow_precision_loss || truncated == 0,
+        MessengerError::PrecisionLoss
+    );
+    Ok(amount / factor)
+}
+
+// Amounts are in base units of the mint, so they only mean what the source chain
+// intended if it assumed the same decimals.
+fn check_decimals(transaction_data: &TransactionData, mint_info: &AccountInfo) -> Result<()> {
+    if let Some(decimals) = transaction_data.decimals {
+        let mint = Account::<Mint>::try_from(mint_info)?;
+        require!(mint.decimals == decimals, MessengerError::DecimalsMismatch);
+    }
+    Ok(())
+}
+
+// Amount math goes through these rather than raw operators so it can never wrap.
+fn checked_add(a: u64, b: u64) -> Result<u64> {
+    a.checked_add(b)
+        .ok_or_else(|| MessengerError::MathOverflow.into())
+}
+
+fn checked_sub(a: u64, b: u64) -> Result<u64> {
+    a.checked_sub(b)
+        .ok_or_else(|| MessengerError::MathOverflow.into())
+}
+
+fn checked_mul(a: u64, b: u64) -> Result<u64> {
+    a.checked_mul(b)
+        .ok_or_else(|| MessengerError::MathOverflow.into())
+}
+
+fn checked_pow10(exponent: u8) -> Result<u64> {
+    10u64
+        .checked_pow(exponent as u32)
+        .ok_or_else(|| MessengerError::MathOverflow.into())
+}
+
+// Fee of bps basis points on amount, rounded up. More than MAX_FEE_BASIS_POINTS
+// would take more than amount.
+fn checked_fee(amount: u64, bps: u16) -> Result<u64> {
+    require!(
+        bps as u128 <= MAX_FEE_BASIS_POINTS,
+        MessengerError::MathOverflow
+    );
+    let product = amount as u128 * bps as u128;
+    let remainder = product % MAX_FEE_BASIS_POINTS;
+    let fee = product / MAX_FEE_BASIS_POINTS + u128::from(remainder > 0);
+    u64::try_from(fee).map_err(|_| MessengerError::MathOverflow.into())
+}
+
+// Token-2022 mints with a TransferFeeConfig deliver amount less the fee of the
+// current epoch, any other mint delivers amount as it is.
+fn post_fee_amount(mint: &AccountInfo, amount: u64, epoch: u64) -> Result<u64> {
+    let fee = if mint.owner == &TOKEN_2022_PROGRAM_ID {
+        transfer_fee(&mint.try_borrow_data()?, amount, epoch)?
+    } else {
+        0
+    };
+    let received = checked_sub(amount, fee)?;
+    require!(received > 0, MessengerError::FeeConsumesAmount);
+    Ok(received)
+}
+
+// The fee Token-2022 charges on amount, as calculate_fee does: checked_fee capped at
+// the maximum fee.
+fn transfer_fee(mint_data: &[u8], amount: u64, epoch: u64) -> Result<u64> {
+    let mut offset = TOKEN_2022_EXTENSIONS_OFFSET;
+    while let Some(header) = mint_data.get(offset..offset + 4) {
+        let extension = u16::from_le_bytes([header[0], header[1]]);
+        let len = u16::from_le_bytes([header[2], header[3]]) as usize;
+        offset += 4;
+        if extension != TRANSFER_FEE_CONFIG_EXTENSION {
+            offset += len;
+            continue;
+        }
+
+        let config = mint_data
+            .get(offset..offset + len)
+            .filter(|config| config.len() == TRANSFER_FEE_CONFIG_LEN)
+            .ok_or(anchor_lang::error::ErrorCode::AccountDidNotDeserialize)?;
+        let (older, newer) = config[72..].split_at(18);
+        let fee = if epoch >= get_u64_le(&newer[..8]) {
+            newer
+        } else {
+            older
+        };
+        let maximum_fee = get_u64_le(&fee[8..16]);
+        let basis_points = u16::from_le_bytes([fee[16], fee[17]]);
+        return Ok(checked_fee(amount, basis_points)?.min(maximum_fee));
+    }
+    Ok(0)
+}
+
+fn get_u64_le(bytes: &[u8]) -> u64 {
+    let mut buf = [0u8; 8];
+    buf.copy_from_slice(bytes);
+    u64::from_le_bytes(buf)
+}
+
+// What the token bridge drops of a native amount when the mint has more decimals
+// than it carries.
+fn bridge_dust(amount: u64, decimals: u8) -> u64 {
+    if decimals <= BRIDGE_MAX_DECIMALS {
+        return 0;
+    }
+    match 10u64.checked_pow((decimals - BRIDGE_MAX_DECIMALS) as u32) {
+        Some(factor) => amount % factor,
+        None => amount,
+    }
+}
+
+// Reported only when the bridge actually dropped something.
+fn dust_truncated(mint: Pubkey, lost_amount: u64) -> Option<DustTruncated> {
+    if lost_amount == 0 {
+        return None;
+    }
+    Some(DustTruncated {
+        mint: mint,
+        lost_amount: lost_amount,
+    })
+}
+
+// Dust isn't worth the bridge fee, checked on the amount actually bridged.
+fn check_min_transfer_amount(config: &Config, amount: u64) -> Result<()> {
+    require!(
+        amount >= config.min_transfer_amount,
+        MessengerError::AmountBelowMinimum
+    );
+    Ok(())
+}
+
+// Amount to delegate to the token bridge. Approving more than the balance succeeds
+// but the bridge transfer then fails deep inside the CPI, so reject it up front, or
+// bridge what is available when config.cap_approval_to_balance is set.
+fn approval_amount(config: &Config, from: Pubkey, requested: u64, balance: u64) -> Result<u64> {
+    if requested <= balance {
+        return Ok(requested);
+    }
+
+    require!(
+        config.cap_approval_to_balance && balance > 0,
+        MessengerError::InsufficientBalance
+    );
+
+    emit!(PartialApproval {
+        from: from,
+        requested: requested,
+        approved: balance
+    });
+    Ok(balance)
+}
+
+// What the payload parsers need besides the DataStorage account they fill in.
+struct MsgEnv<'a, 'info> {
+    config: &'a Config,
+    remaining_accounts: &'a [AccountInfo<'info>],
+    // Decimals amounts are encoded with on the emitter chain, 0 when already in mint units.
+    source_decimals: u8,
+    native_chain_id: u64,
+}
+
+// Hash a VAA, check it sits at the address the core bridge derives for it and was
+// emitted by the registered emitter.
+fn verify_vaa(
+    config: &Config,
+    core_bridge_vaa: &AccountInfo,
+    emitter_acc: &EmitterAddrAccount,
+) -> Result<MessageData> {
+    // Only the core bridge can have written a posted VAA, whatever the data says.
+    require!(
+        core_bridge_vaa.owner == &config.core_bridge,
+        MessengerError::InvalidVaaOwner
+    );
+    let vaa = PostedMessageData::try_from_slice(&core_bridge_vaa.data.borrow())?.0;
+
+    // The cheap checks go first so VAAs from unregistered emitters are turned away
+    // before paying for the serialization and hash below.
+    // Check that the emitter chain and address match up with the vaa
+    require!(
+        vaa.emitter_chain == emitter_acc.chain_id,
+        MessengerError::WrongEmitterChain
+    );
+    require!(
+        vaa.emitter_address == emitter_acc.emitter_bytes,
+        MessengerError::WrongEmitterAddress
+    );
+
+    // Messages below the configured finality could still be reorged out on the source chain.
+    require!(
+        vaa.consistency_level >= config.min_consistency_level,
+        MessengerError::InsufficientFinality
+    );
+
+    //Hash a VAA Extract and derive a VAA Key
+    let (vaa_key, vaa_hash) = posted_vaa_key(config, &vaa)?;
+
+    require!(
+        core_bridge_vaa.key() == vaa_key,
+        MessengerError::VAAKeyMismatch
+    );
+
+    emit!(VaaVerified {
+        vaa_key: vaa_key,
+        vaa_hash: vaa_hash
+    });
+
+    Ok(vaa)
+}
+
+// Decode the payload of a verified VAA into transaction_data based upon the code
+// sent on message passing.
+fn dispatch_msg(
+    vaa: &MessageData,
+    current_count: u64,
+    sender: [u8; 32],
+    transaction_data: &mut TransactionData,
+    env: &MsgEnv,
+) -> Result<()> {
+    // Encoded String
+    let (version, body) = payload::split_version(&vaa.payload)?;
+    let encoded_str = match version {
+        PayloadVersion::V1 => body.to_vec(),
+        PayloadVersion::V2 => return Err(MessengerError::UnsupportedPayloadVersion.into()),
+    };
+
+    let code = get_u8(encoded_str[0..1].to_vec());
+    let max_len = if code == MessageCode::Relay as u64 {
+        MAX_RELAY_PAYLOAD_LEN
+    } else {
+        MAX_PAYLOAD_LEN
+    };
+    if encoded_str.len() > max_len {
+        payload::PayloadError {
+            opcode: code as u8,
+            offset: max_len,
+            expected: max_len,
+            got: encoded_str.len(),
+        }
+        .log();
+        return Err(MessengerError::PayloadTooLarge.into());
+    }
+
+    emit!(StoredMsg {
+        msg_type: code,
+        sender: sender,
+        count: current_count
+    });
+
+    decode_payload(
+        encoded_str,
+        vaa.emitter_chain,
+        sender.to_vec(),
+        transaction_data,
+        env,
+    )
+}
+
+// Parse a payload, opcode included, into transaction_data. Shared by store_msg and
+// the decode_only dry run.
+fn decode_payload(
+    encoded_str: Vec<u8>,
+    from_chain_id: u16,
+    sender: Vec<u8>,
+    transaction_data: &mut TransactionData,
+    env: &MsgEnv,
+) -> Result<()> {
+    let code = get_u8(encoded_str[0..1].to_vec());
+
+    let message_code = match MessageCode::try_from(code) {
+        Ok(message_code) => message_code,
+        Err(err) => {
+            // The error alone doesn't say which opcode the encoder sent.
+            msg!("Unknown opcode {} from chain {}", code, from_chain_id);
+            if env.config.log_rejected_codes {
+                emit!(UnsupportedCode {
+                    code: code,
+                    emitter_chain: from_chain_id
+                });
+            }
+            return Err(err.into());
+        }
+    };
+    transaction_data.program_id = message_code.program_id(env.config.token_bridge);
+    transaction_data.msg_type = code as u8;
+    transaction_data.version = DATA_STORAGE_VERSION;
+    transaction_data.native_chain_id = env.native_chain_id;
+
+    // Switch Based on the code
+    match message_code {
+        MessageCode::Stream => {
+            process_stream(encoded_str, from_chain_id, transaction_data, env, sender)
+        }
+        MessageCode::WithdrawStream => {
+            process_withdraw_stream(encoded_str, from_chain_id, transaction_data, env, sender)
+        }
+        MessageCode::Deposit => {
+            process_deposit(encoded_str, from_chain_id, transaction_data, env, sender)
+        }
+        MessageCode::Pause => {
+            process_pause(encoded_str, from_chain_id, transaction_data, env, sender)
+        }
+        MessageCode::Withdraw => {
+            process_withdraw(encoded_str, from_chain_id, transaction_data, env, sender)
+        }
+        MessageCode::InstantTransfer => {
+            process_instant_transfer(encoded_str, from_chain_id, transaction_data, env, sender)
+        }
+        MessageCode::UpdateStream => {
+            process_update_stream(encoded_str, from_chain_id, transaction_data, env, sender)
+        }
+        MessageCode::CancelStream => {
+            process_cancel_stream(encoded_str, from_chain_id, transaction_data, env, sender)
+        }
+        MessageCode::DirectTransfer => {
+            process_direct_transfer(encoded_str, from_chain_id, transaction_data, env, sender)
+        }
+        MessageCode::StreamWithCliff => {
+            process_stream_with_cliff(encoded_str, from_chain_id, transaction_data, env, sender)
+        }
+        MessageCode::Relay => {
+            process_relay(encoded_str, from_chain_id, transaction_data, env, sender)
+        }
+    }?;
+
+    // Relay payloads don't carry a mint.
+    if message_code != MessageCode::Relay {
+        check_mint_allowed(env.config, transaction_data.token_mint)?;
+    }
+    Ok(())
+}
+
+// A Zebec instruction built on the emitter chain, borsh encoded as a RelayInstruction
+// after the sender. Only its hash is stored; the program has to be the one the Relay
+// code allows, which dispatch_msg already put in program_id.
+fn process_relay(
+    encoded_str: Vec<u8>,
+    from_chain_id: u16,
+    transaction_data: &mut TransactionData,
+    _env: &MsgEnv,
+    sender: Vec<u8>,
+) -> Result<()> {
+    let parsed = payload::parse_relay(&encoded_str)?;
+
+    require!(
+        parsed.instruction.program_id == transaction_data.program_id,
+        MessengerError::UnexpectedProgramId
+    );
+
+    transaction_data.sender = parsed.sender.clone();
+    transaction_data.from_chain_id = from_chain_id as u64;
+    transaction_data.instruction_hash = hash_instruction(&parsed.instruction_bytes);
+
+    require!(parsed.sender == sender, MessengerError::InvalidSenderWallet);
+
+    emit!(RelayParsed {
+        sender: transaction_data.sender.clone(),
+        program_id: parsed.instruction.program_id,
+        instruction_hash: transaction_data.instruction_hash
+    });
+    Ok(())
+}
+
+fn hash_instruction(instruction_bytes: &[u8]) -> [u8; 32] {
+    let mut h = sha3::Keccak256::default();
+    h.update(instruction_bytes);
+    h.finalize().into()
+}
+
+// Estimated compute units to store one batched message, see STORE_MSG_BASE_CU.
+fn estimate_store_cost(accounts: &[AccountInfo]) -> u64 {
+    // processed_vaa, data_storage and txn_status are always created, txn_count on first use.
+    let created = if accounts[3].data_is_empty() { 4 } else { 3 };
+    let payload_len = accounts[0].data_len() as u64;
+
+    STORE_MSG_BASE_CU
+        + created * STORE_MSG_CREATE_ACCOUNT_CU
+        + payload_len * STORE_MSG_PAYLOAD_BYTE_CU
+}
+
+// One message of store_msg_batch. Does by hand what the init constraints of
+// StoreMsg do, since the accounts arrive through remaining_accounts.
+#[allow(clippy::too_many_arguments)]
+fn store_batched_msg<'info>(
+    program_id: &Pubkey,
+    payer: &Signer<'info>,
+    system_program: &Program<'info, System>,
+    emitter_acc: &EmitterAddrAccount,
+    accounts: &[AccountInfo<'info>],
+    sender: [u8; 32],
+    env: &MsgEnv,
+    daily_stats: &mut DailyStats,
+    day: u64,
+) -> Result<()> {
+    let core_bridge_vaa = &accounts[0];
+    let processed_vaa = &accounts[1];
+    let data_storage = &accounts[2];
+    let txn_count = &accounts[3];
+    let txn_status = &accounts[4];
+
+    let vaa = verify_vaa(env.config, core_bridge_vaa, emitter_acc)?;
+
+    // Fails if the VAA was already processed, as with the init constraint.
+    create_pda_account(
+        payer,
+        system_program,
+        processed_vaa,
+        &[
+            &emitter_acc.emitter_bytes[..],
+            emitter_acc.chain_id.to_be_bytes().as_ref(),
+            vaa.sequence.to_be_bytes().as_ref(),
+        ],
+        PROCESSED_VAA_SPACE,
+        program_id,
+    )?;
+    write_account(processed_vaa, &ProcessedVAA {})?;
+
+    let count_seeds: &[&[u8]] = &[b"txn_count".as_ref(), &sender];
+    let mut count = if txn_count.data_is_empty() {
+        create_pda_account(
+            payer,
+            system_program,
+            txn_count,
+            count_seeds,
+            TXN_COUNT_SPACE,
+            program_id,
+        )?;
+        Count::default()
+    } else {
+        let (count_key, _) = Pubkey::find_program_address(count_seeds, program_id);
+        require!(
+            txn_count.key() == count_key,
+            anchor_lang::error::ErrorCode::ConstraintSeeds
+        );
+        Account::<Count>::try_from(txn_count)?.into_inner()
+    };
+    // Keyed by the incremented count, as in store_msg.
+    let current_count = next_count(&count)?;
+
+    create_pda_account(
+        payer,
+        system_program,
+        data_storage,
+        &[
+            b"data_store".as_ref(),
+            &sender,
+            current_count.to_be_bytes().as_ref(),
+        ],
+        DATA_STORAGE_SPACE,
+        program_id,
+    )?;
+    create_pda_account(
+        payer,
+        system_program,
+        txn_status,
+        &[
+            b"txn_status".as_ref(),
+            &sender,
+            current_count.to_be_bytes().as_ref(),
+        ],
+        TXN_STATUS_SPACE,
+        program_id,
+    )?;
+
+    let mut transaction_data = TransactionData::default();
+    dispatch_msg(&vaa, current_count, sender, &mut transaction_data, env)?;
+    emit_lifecycle(
+        data_storage.key(),
+        OperationStage::Stored,
+        OperationStatus::Succeeded,
+    );
+    record_daily_stats(
+        daily_stats,
+        day,
+        transaction_data.token_mint,
+        transaction_data.amount,
+    );
+    count.count = current_count;
+
+    write_account(data_storage, &transaction_data)?;
+    write_account(txn_count, &count)?;
+    write_account(
+        txn_status,
+        &TransactionStatus {
+            executed: false,
+            staged: false,
+            created: false,
+        },
+    )
+}
+
+// Keep Config.registered_chains in step with the emitter accounts.
+fn index_registered_chain(config: &mut Config, chain_id: u16) -> Result<()> {
+    if !config.registered_chains.contains(&chain_id) {
+        require!(
+            config.registered_chains.len() < MAX_REGISTERED_CHAINS,
+            MessengerError::TooManyRegisteredChains
+        );
+        config.registered_chains.push(chain_id);
+    }
+    Ok(())
+}
+
+// Create a program owned account at the PDA derived from seeds, failing if the
+// passed account is not that PDA or already exists.
+// Count the sender's next message is stored under. The create and execute contexts
+// seed data_store and txn_status with txn_count after it was incremented.
+fn next_count(txn_count: &Count) -> Result<u64> {
+    txn_count
+        .count
+        .checked_add(1)
+        .ok_or_else(|| MessengerError::Overflow.into())
+}
+
+fn create_pda_account<'info>(
+    payer: &Signer<'info>,
+    system_program: &Program<'info, System>,
+    target: &AccountInfo<'info>,
+    seeds: &[&[u8]],
+    space: usize,
+    program_id: &Pubkey,
+) -> Result<()> {
+    let (key, bump) = Pubkey::find_program_address(seeds, program_id);
+    require!(
+        target.key() == key,
+        anchor_lang::error::ErrorCode::ConstraintSeeds
+    );
+
+    let bump = [bump];
+    let mut signer_seeds = seeds.to_vec();
+    signer_seeds.push(&bump);
+
+    let ix = solana_program::system_instruction::create_account(
+        payer.key,
+        target.key,
+        Rent::get()?.minimum_balance(space),
+        space as u64,
+        program_id,
+    );
+    invoke_signed(
+        &ix,
+        &[
+            payer.to_account_info(),
+            target.clone(),
+            system_program.to_account_info(),
+        ],
+        &[&signer_seeds[..]],
+    )?;
+    Ok(())
+}
+
+// Realloc an account of this program to space, topping its rent up from payer.
+// Accounts already that large are left as they are.
+fn grow_account<'info>(
+    payer: &Signer<'info>,
+    system_program: &Program<'info, System>,
+    account: &AccountInfo<'info>,
+    space: usize,
+) -> Result<()> {
+    if account.data_len() >= space {
+        return Ok(());
+    }
+    let lamports = Rent::get()?
+        .minimum_balance(space)
+        .saturating_sub(account.lamports());
+    if lamports > 0 {
+        solana_program::program::invoke(
+            &solana_program::system_instruction::transfer(payer.key, account.key, lamports),
+            &[
+                payer.to_account_info(),
+                account.clone(),
+                system_program.to_account_info(),
+            ],
+        )?;
+    }
+    account.realloc(space, true)?;
+    Ok(())
+}
 
-        let bump = ctx.bumps.get("pda_signer").unwrap().to_le_bytes();
+// Hand the lamports of an account this program owns to destination and wipe it, the
+// runtime then drops it at the end of the transaction.
+fn close_legacy_account(account: &AccountInfo, destination: &AccountInfo) -> Result<()> {
+    let total = checked_add(destination.lamports(), account.lamports())?;
+    **destination.try_borrow_mut_lamports()? = total;
+    **account.try_borrow_mut_lamports()? = 0;
+    account.try_borrow_mut_data()?.fill(0);
+    Ok(())
+}
 
-        let signer_seeds: &[&[&[u8]]] = &[&[&sender, &sender_chain, &bump]];
+// Rewrite a DataStorage account already grown to DATA_STORAGE_SPACE under the
+// current version, returns the version it was stored with.
+fn upgrade_data_storage(
+    data_storage: &AccountInfo,
+    stream_data_account: Option<Pubkey>,
+) -> Result<u8> {
+    let mut transaction_data = Account::<TransactionData>::try_from(data_storage)?.into_inner();
+    let from_version = transaction_data.version;
+    transaction_data.version = DATA_STORAGE_VERSION;
+    if let Some(stream_data_account) = stream_data_account {
+        check_operation(
+            &transaction_data,
+            &[MessageCode::Stream, MessageCode::StreamWithCliff],
+        )?;
+        // Never repoint a stream that already has one.
+        require!(
+            transaction_data.data_account == Pubkey::default(),
+            MessengerError::DataAccountMismatch
+        );
+        transaction_data.data_account = stream_data_account;
+    }
+    write_account(data_storage, &transaction_data)?;
+    Ok(from_version)
+}
 
-        let approve_ctx = CpiContext::new_with_signer(
-            ctx.accounts.token_program.to_account_info(),
-            Approve {
-                to: ctx.accounts.from.to_account_info(),
-                delegate: ctx.accounts.portal_authority_signer.to_account_info(),
-                authority: ctx.accounts.pda_signer.to_account_info(),
-            },
-            signer_seeds,
-        );
-
-        // Delgate transfer authority to Token Bridge for the tokens
-        approve(approve_ctx, amount)?;
-
-        let target_address: [u8; 32] = receiver.as_slice().try_into().unwrap();
-        // Instruction
-        let transfer_ix = Instruction {
-            program_id: Pubkey::from_str(TOKEN_BRIDGE_ADDRESS).unwrap(),
-            accounts: vec![
-                AccountMeta::new(ctx.accounts.zebec_eoa.key(), true),
-                AccountMeta::new_readonly(ctx.accounts.portal_config.key(), false),
-                AccountMeta::new(ctx.accounts.from.key(), false),
-                AccountMeta::new(ctx.accounts.mint.key(), false),
-                AccountMeta::new(ctx.accounts.portal_custody.key(), false),
-                AccountMeta::new_readonly(ctx.accounts.portal_authority_signer.key(), false),
-                AccountMeta::new_readonly(ctx.accounts.portal_custody_signer.key(), false),
-                AccountMeta::new(ctx.accounts.bridge_config.key(), false),
-                AccountMeta::new(ctx.accounts.portal_message.key(), true),
-                AccountMeta::new_readonly(ctx.accounts.portal_emitter.key(), false),
-                AccountMeta::new(ctx.accounts.portal_sequence.key(), false),
-                AccountMeta::new(ctx.accounts.bridge_fee_collector.key(), false),
-                AccountMeta::new_readonly(ctx.accounts.clock.key(), false),
-                // Dependencies
-                AccountMeta::new_readonly(ctx.accounts.rent.key(), false),
-                AccountMeta::new_readonly(ctx.accounts.system_program.key(), false),
-                // Program
-                AccountMeta::new_readonly(ctx.accounts.core_bridge_program.key(), false),
-                AccountMeta::new_readonly(ctx.accounts.token_program.key(), false),
-            ],
-            data: (
-                crate::portal::Instruction::TransferNative,
-                TransferNativeData {
-                    nonce: ctx.accounts.config.nonce,
-                    amount,
-                    fee,
-                    target_address,
-                    target_chain,
-                },
-            )
-                .try_to_vec()?,
-        };
+fn write_account<T: AccountSerialize>(info: &AccountInfo, account: &T) -> Result<()> {
+    let mut data = info.try_borrow_mut_data()?;
+    let mut writer: &mut [u8] = &mut data[..];
+    account.try_serialize(&mut writer)
+}
 
-        // Accounts
-        let transfer_accs = vec![
-            ctx.accounts.zebec_eoa.to_account_info(),
-            ctx.accounts.portal_config.to_account_info(),
-            ctx.accounts.from.to_account_info(),
-            ctx.accounts.mint.to_account_info(),
-            ctx.accounts.portal_custody.to_account_info(),
-            ctx.accounts.portal_authority_signer.to_account_info(),
-            ctx.accounts.portal_custody_signer.to_account_info(),
-            ctx.accounts.bridge_config.to_account_info(),
-            ctx.accounts.portal_message.to_account_info(),
-            ctx.accounts.portal_emitter.to_account_info(),
-            ctx.accounts.portal_sequence.to_account_info(),
-            ctx.accounts.bridge_fee_collector.to_account_info(),
-            ctx.accounts.clock.to_account_info(),
-            // Dependencies
-            ctx.accounts.rent.to_account_info(),
-            ctx.accounts.system_program.to_account_info(),
-            // Program
-            ctx.accounts.core_bridge_program.to_account_info(),
-            ctx.accounts.token_program.to_account_info(),
-        ];
+// Payloads may carry a deadline after their fixed layout. Older encoders don't
+// send it, in which case the operation never expires.
+fn get_deadline(encoded_str: &[u8], offset: usize) -> u64 {
+    get_optional_u64(encoded_str, offset)
+}
 
-        invoke_signed(&transfer_ix, &transfer_accs, signer_seeds)?;
+// Trailing fields are optional, missing ones read as 0.
+fn get_optional_u64(encoded_str: &[u8], offset: usize) -> u64 {
+    match encoded_str.get(offset..offset + 8) {
+        Some(bytes) => get_u64(bytes.to_vec()),
+        None => 0,
+    }
+}
 
-        let sum = ctx.accounts.config.nonce.checked_add(1);
-        match sum {
-            None => return Err(MessengerError::Overflow.into()),
-            Some(val) => ctx.accounts.config.nonce = val,
-        }
+// A deadline of 0 means no per-operation expiry.
+fn check_deadline(transaction_data: &TransactionData) -> Result<()> {
+    let now = Clock::get()?.unix_timestamp;
+    require!(
+        transaction_data.deadline == 0 || now as u64 <= transaction_data.deadline,
+        MessengerError::OperationExpired
+    );
+    Ok(())
+}
 
-        Ok(())
-    }
+// Payload slices are untrusted, a wrong length is an error instead of a panic.
+fn get_pubkey(data_bytes: &[u8]) -> Result<Pubkey> {
+    let data_u8 =
+        <[u8; 32]>::try_from(data_bytes).map_err(|_| MessengerError::InvalidPubkeyBytes)?;
+    Ok(Pubkey::new_from_array(data_u8))
 }
 
 fn get_u64(data_bytes: Vec<u8>) -> u64 {
@@ -1163,6 +4004,13 @@ fn get_u256(data_bytes: Vec<u8>) -> U256 {
     U256::from_big_endian(&data_u8)
 }
 
+// For U256 fields that have to fit a narrower domain, e.g. a chain id.
+fn get_u256_bounded(data_bytes: Vec<u8>, max: U256) -> Result<U256> {
+    let value = get_u256(data_bytes);
+    require!(value <= max, MessengerError::ValueOutOfRange);
+    Ok(value)
+}
+
 fn get_u8(data_bytes: Vec<u8>) -> u64 {
     let prefix_bytes = vec![0; 7];
     let joined_bytes = [prefix_bytes, data_bytes].concat();
@@ -1170,133 +4018,228 @@ fn get_u8(data_bytes: Vec<u8>) -> u64 {
     u64::from_be_bytes(data_u8)
 }
 
+// Key of the PostedVAA account the core bridge creates for the given VAA body.
+fn posted_vaa_key(config: &Config, vaa: &MessageData) -> Result<(Pubkey, [u8; 32])> {
+    let serialized_vaa = serialize_vaa(vaa)?;
+
+    let mut h = sha3::Keccak256::default();
+    h.write_all(serialized_vaa.as_slice())
+        .map_err(|_| MessengerError::VaaSerializationFailed)?;
+    let vaa_hash: [u8; 32] = h.finalize().into();
+
+    let (vaa_key, _) =
+        Pubkey::find_program_address(&[b"PostedVAA", &vaa_hash], &config.core_bridge);
+    Ok((vaa_key, vaa_hash))
+}
+
+// Parse a raw signed VAA: version, guardian set index, signatures, then the body
+// fields in the same order serialize_vaa writes them.
+fn parse_raw_vaa(raw_vaa: &[u8]) -> std::result::Result<MessageData, MessengerError> {
+    read_raw_vaa(&mut Cursor::new(raw_vaa)).map_err(|_| MessengerError::MalformedVaa)
+}
+
+fn read_raw_vaa(v: &mut Cursor<&[u8]>) -> std::io::Result<MessageData> {
+    let vaa_version = v.read_u8()?;
+    let _guardian_set_index = v.read_u32::<BigEndian>()?;
+    let signatures = v.read_u8()? as u64;
+    // guardian index + 65 byte secp256k1 signature
+    v.set_position(v.position() + signatures * 66);
+
+    let vaa_time = v.read_u32::<BigEndian>()?;
+    let nonce = v.read_u32::<BigEndian>()?;
+    let emitter_chain = v.read_u16::<BigEndian>()?;
+    let mut emitter_address = [0u8; 32];
+    v.read_exact(&mut emitter_address)?;
+    let sequence = v.read_u64::<BigEndian>()?;
+    let consistency_level = v.read_u8()?;
+    let mut payload = vec![];
+    v.read_to_end(&mut payload)?;
+
+    Ok(MessageData {
+        vaa_version,
+        consistency_level,
+        vaa_time,
+        nonce,
+        sequence,
+        emitter_chain,
+        emitter_address,
+        payload,
+        ..Default::default()
+    })
+}
+
 // Convert a full VAA structure into the serialization of its unique components, this structure is
 // what is hashed and verified by Guardians.
-pub fn serialize_vaa(vaa: &MessageData) -> Vec<u8> {
+pub fn serialize_vaa(vaa: &MessageData) -> std::result::Result<Vec<u8>, MessengerError> {
     let mut v = Cursor::new(Vec::new());
-    v.write_u32::<BigEndian>(vaa.vaa_time).unwrap();
-    v.write_u32::<BigEndian>(vaa.nonce).unwrap();
-    v.write_u16::<BigEndian>(vaa.emitter_chain as u16).unwrap();
-    v.write_all(&vaa.emitter_address).unwrap();
-    v.write_u64::<BigEndian>(vaa.sequence).unwrap();
-    v.write_u8(vaa.consistency_level).unwrap();
-    v.write_all(&vaa.payload).unwrap();
-    v.into_inner()
+    write_vaa(&mut v, vaa).map_err(|_| MessengerError::VaaSerializationFailed)?;
+    Ok(v.into_inner())
+}
+
+fn write_vaa(v: &mut Cursor<Vec<u8>>, vaa: &MessageData) -> std::io::Result<()> {
+    v.write_u32::<BigEndian>(vaa.vaa_time)?;
+    v.write_u32::<BigEndian>(vaa.nonce)?;
+    v.write_u16::<BigEndian>(vaa.emitter_chain)?;
+    v.write_all(&vaa.emitter_address)?;
+    v.write_u64::<BigEndian>(vaa.sequence)?;
+    v.write_u8(vaa.consistency_level)?;
+    v.write_all(&vaa.payload)
 }
 
+// The process_* functions write a parsed payload into transaction_data, after the
+// checks that need the VAA sender or the config.
 fn process_deposit(
     encoded_str: Vec<u8>,
     from_chain_id: u16,
-    ctx: Context<StoreMsg>,
+    transaction_data: &mut TransactionData,
+    env: &MsgEnv,
     sender: Vec<u8>,
 ) -> Result<()> {
-    let transaction_data = &mut ctx.accounts.data_storage;
-
-    let amount = get_u64(encoded_str[1..9].to_vec());
-    let _to_chain_id = get_u256(encoded_str[9..41].to_vec());
-    let senderbytes = encoded_str[41..73].to_vec();
-    let token_mint_bytes = &encoded_str[73..105].to_vec();
+    let parsed = payload::parse_deposit(&encoded_str)?;
 
-    transaction_data.amount = amount;
-    transaction_data.sender = senderbytes.clone();
+    transaction_data.sender = parsed.wallet.clone();
     transaction_data.from_chain_id = from_chain_id as u64;
-    transaction_data.token_mint = Pubkey::new(&token_mint_bytes);
+    transaction_data.deadline = parsed.deadline;
+    transaction_data.token_mint = parsed.token_mint;
 
-    require!(senderbytes == sender, MessengerError::InvalidSenderWallet);
+    transaction_data.amount = normalize_amount(env, parsed.token_mint, parsed.amount)?;
+    check_amount_plausibility(env, transaction_data.token_mint, transaction_data.amount)?;
+
+    require!(parsed.wallet == sender, MessengerError::InvalidSenderWallet);
+
+    emit!(DepositParsed {
+        sender: transaction_data.sender.clone(),
+        token_mint: transaction_data.token_mint,
+        amount: transaction_data.amount,
+        deadline: transaction_data.deadline
+    });
     Ok(())
 }
 
 fn process_stream(
     encoded_str: Vec<u8>,
     from_chain_id: u16,
-    ctx: Context<StoreMsg>,
+    transaction_data: &mut TransactionData,
+    env: &MsgEnv,
     sender: Vec<u8>,
 ) -> Result<()> {
-    let transaction_data = &mut ctx.accounts.data_storage;
-    let start_time = get_u64(encoded_str[1..9].to_vec());
-    let end_time = get_u64(encoded_str[9..17].to_vec());
-    let amount = get_u64(encoded_str[17..25].to_vec());
-    let _to_chain_id = get_u256(encoded_str[25..57].to_vec());
-    let senderwallet_bytes = encoded_str[57..89].to_vec();
-    let receiver_wallet_bytes = encoded_str[89..121].to_vec();
-    let can_update = get_u64(encoded_str[121..129].to_vec());
-    let can_cancel = get_u64(encoded_str[129..137].to_vec());
-    let token_mint_bytes = &encoded_str[137..169].to_vec();
-
-    transaction_data.start_time = start_time;
-    transaction_data.end_time = end_time;
-
-    transaction_data.can_update = can_update == 1;
-    transaction_data.can_cancel = can_cancel == 1;
-
-    transaction_data.amount = amount;
-    transaction_data.sender = senderwallet_bytes.clone();
-    transaction_data.receiver = receiver_wallet_bytes;
-    transaction_data.from_chain_id = from_chain_id as u64;
-    transaction_data.token_mint = Pubkey::new(&token_mint_bytes);
+    let parsed = payload::parse_stream(&encoded_str)?;
+    write_stream(parsed, from_chain_id, transaction_data, env, sender)
+}
 
-    require!(
-        senderwallet_bytes == sender,
-        MessengerError::InvalidSenderWallet
-    );
+fn process_stream_with_cliff(
+    encoded_str: Vec<u8>,
+    from_chain_id: u16,
+    transaction_data: &mut TransactionData,
+    env: &MsgEnv,
+    sender: Vec<u8>,
+) -> Result<()> {
+    let parsed = payload::parse_stream_with_cliff(&encoded_str)?;
+    write_stream(parsed, from_chain_id, transaction_data, env, sender)
+}
+
+fn write_stream(
+    parsed: payload::StreamPayload,
+    from_chain_id: u16,
+    transaction_data: &mut TransactionData,
+    env: &MsgEnv,
+    sender: Vec<u8>,
+) -> Result<()> {
+    transaction_data.start_time = parsed.start_time;
+    transaction_data.end_time = parsed.end_time;
+    transaction_data.cliff_time = parsed.cliff_time;
+
+    transaction_data.can_update = parsed.can_update;
+    transaction_data.can_cancel = parsed.can_cancel;
+
+    transaction_data.sender = parsed.sender.clone();
+    transaction_data.receiver = parsed.receiver;
+    transaction_data.from_chain_id = from_chain_id as u64;
+    transaction_data.deadline = parsed.deadline;
+    transaction_data.token_mint = parsed.token_mint;
+
+    transaction_data.amount = normalize_amount(env, parsed.token_mint, parsed.amount)?;
+    check_amount_plausibility(env, transaction_data.token_mint, transaction_data.amount)?;
+
+    require!(parsed.sender == sender, MessengerError::InvalidSenderWallet);
+
+    emit!(StreamParsed {
+        msg_type: transaction_data.msg_type,
+        sender: transaction_data.sender.clone(),
+        receiver: transaction_data.receiver.clone(),
+        token_mint: transaction_data.token_mint,
+        amount: transaction_data.amount,
+        start_time: transaction_data.start_time,
+        end_time: transaction_data.end_time,
+        cliff_time: transaction_data.cliff_time,
+        can_update: transaction_data.can_update,
+        can_cancel: transaction_data.can_cancel,
+        deadline: transaction_data.deadline
+    });
     Ok(())
 }
 
 fn process_update_stream(
     encoded_str: Vec<u8>,
     from_chain_id: u16,
-    ctx: Context<StoreMsg>,
+    transaction_data: &mut TransactionData,
+    env: &MsgEnv,
     sender: Vec<u8>,
 ) -> Result<()> {
-    let transaction_data = &mut ctx.accounts.data_storage;
-    let start_time = get_u64(encoded_str[1..9].to_vec());
-    let end_time = get_u64(encoded_str[9..17].to_vec());
-    let amount = get_u64(encoded_str[17..25].to_vec());
-    let _to_chain_id = get_u256(encoded_str[25..57].to_vec());
-    let senderwallet_bytes = encoded_str[57..89].to_vec();
-    let receiver_wallet_bytes = encoded_str[89..121].to_vec();
-    let token_mint = &encoded_str[121..153].to_vec();
-    let data_account = &encoded_str[153..185].to_vec();
-
-    transaction_data.start_time = start_time;
-    transaction_data.end_time = end_time;
-    transaction_data.amount = amount;
-    transaction_data.sender = senderwallet_bytes.clone();
-    transaction_data.receiver = receiver_wallet_bytes;
-    transaction_data.from_chain_id = from_chain_id as u64;
-    transaction_data.token_mint = Pubkey::new(&token_mint);
-    transaction_data.data_account = Pubkey::new(&data_account);
+    let parsed = payload::parse_update_stream(&encoded_str)?;
 
-    require!(
-        senderwallet_bytes == sender,
-        MessengerError::InvalidSenderWallet
-    );
+    transaction_data.start_time = parsed.start_time;
+    transaction_data.end_time = parsed.end_time;
+    transaction_data.sender = parsed.sender.clone();
+    transaction_data.receiver = parsed.receiver;
+    transaction_data.from_chain_id = from_chain_id as u64;
+    transaction_data.deadline = parsed.deadline;
+    transaction_data.token_mint = parsed.token_mint;
+    transaction_data.data_account = parsed.data_account;
+
+    // The stream's DataStorage has to be passed in remaining_accounts, an update to
+    // an immutable stream is never stored. find_stream only accepts accounts this
+    // program created as TransactionData, so it can't be stood in for.
+    let stream = find_stream(transaction_data, env.remaining_accounts)?;
+    require!(stream.can_update, MessengerError::UpdatesNotAllowed);
+
+    transaction_data.amount = normalize_amount(env, parsed.token_mint, parsed.amount)?;
+    check_amount_plausibility(env, transaction_data.token_mint, transaction_data.amount)?;
+
+    require!(parsed.sender == sender, MessengerError::InvalidSenderWallet);
+
+    emit!(StreamUpdateParsed {
+        sender: transaction_data.sender.clone(),
+        receiver: transaction_data.receiver.clone(),
+        token_mint: transaction_data.token_mint,
+        data_account: transaction_data.data_account,
+        amount: transaction_data.amount,
+        start_time: transaction_data.start_time,
+        end_time: transaction_data.end_time,
+        deadline: transaction_data.deadline
+    });
     Ok(())
 }
 
 fn process_pause(
     encoded_str: Vec<u8>,
     from_chain_id: u16,
-    ctx: Context<StoreMsg>,
+    transaction_data: &mut TransactionData,
+    _env: &MsgEnv,
     sender: Vec<u8>,
 ) -> Result<()> {
-    let transaction_data = &mut ctx.accounts.data_storage;
-    let _to_chain_id = get_u256(encoded_str[1..33].to_vec());
-    let depositor_wallet_bytes = encoded_str[33..65].to_vec();
-    let token_mint = encoded_str[65..97].to_vec();
-    let receiver_wallet_bytes = encoded_str[97..129].to_vec();
-    let data_account = encoded_str[129..161].to_vec();
-
-    transaction_data.sender = depositor_wallet_bytes.clone();
-    transaction_data.receiver = receiver_wallet_bytes;
+    let parsed = payload::parse_pause(&encoded_str)?;
+
+    transaction_data.sender = parsed.wallet.clone();
+    transaction_data.receiver = parsed.counterparty;
     transaction_data.from_chain_id = from_chain_id as u64;
-    transaction_data.token_mint = Pubkey::new(&token_mint);
-    transaction_data.data_account = Pubkey::new(&data_account);
+    transaction_data.deadline = parsed.deadline;
+    transaction_data.token_mint = parsed.token_mint;
+    transaction_data.data_account = parsed.data_account;
 
-    require!(
-        depositor_wallet_bytes == sender,
-        MessengerError::InvalidSenderWallet
-    );
+    require!(parsed.wallet == sender, MessengerError::InvalidSenderWallet);
+
+    emit_stream_action(transaction_data);
     Ok(())
 }
 
@@ -1304,160 +4247,981 @@ fn process_pause(
 fn process_withdraw_stream(
     encoded_str: Vec<u8>,
     from_chain_id: u16,
-    ctx: Context<StoreMsg>,
+    transaction_data: &mut TransactionData,
+    env: &MsgEnv,
     receiver: Vec<u8>,
 ) -> Result<()> {
-    let transaction_data = &mut ctx.accounts.data_storage;
-    let _to_chain_id = get_u256(encoded_str[1..33].to_vec());
-    let withdrawer_wallet_bytes = encoded_str[33..65].to_vec();
-    let token_mint = encoded_str[65..97].to_vec();
-    let depositor_wallet_bytes = encoded_str[97..129].to_vec();
-    let data_account = encoded_str[129..161].to_vec();
-
-    transaction_data.sender = depositor_wallet_bytes;
-    transaction_data.receiver = withdrawer_wallet_bytes.clone();
+    let parsed = payload::parse_withdraw_stream(&encoded_str)?;
+
+    transaction_data.sender = parsed.counterparty;
+    transaction_data.receiver = parsed.wallet.clone();
     transaction_data.from_chain_id = from_chain_id as u64;
-    transaction_data.token_mint = Pubkey::new(&token_mint);
-    transaction_data.data_account = Pubkey::new(&data_account);
+    transaction_data.deadline = parsed.deadline;
+    transaction_data.token_mint = parsed.token_mint;
+    transaction_data.data_account = parsed.data_account;
+
+    // 0 withdraws everything vested.
+    transaction_data.amount = normalize_amount(env, parsed.token_mint, parsed.amount)?;
 
     require!(
-        withdrawer_wallet_bytes.to_vec() == receiver,
+        parsed.wallet == receiver,
         MessengerError::InvalidSenderWallet
     );
+
+    emit_stream_action(transaction_data);
     Ok(())
 }
 
 fn process_cancel_stream(
     encoded_str: Vec<u8>,
     from_chain_id: u16,
-    ctx: Context<StoreMsg>,
+    transaction_data: &mut TransactionData,
+    env: &MsgEnv,
     sender: Vec<u8>,
 ) -> Result<()> {
-    let transaction_data = &mut ctx.accounts.data_storage;
-    let _to_chain_id = get_u256(encoded_str[1..33].to_vec());
-    let depositor_wallet_bytes = encoded_str[33..65].to_vec();
-    let token_mint = encoded_str[65..97].to_vec();
-    let receiver_wallet_bytes = encoded_str[97..129].to_vec();
-    let data_account = encoded_str[129..161].to_vec();
-
-    transaction_data.sender = depositor_wallet_bytes.clone();
-    transaction_data.receiver = receiver_wallet_bytes;
-    transaction_data.from_chain_id = from_chain_id as u64;
-    transaction_data.token_mint = Pubkey::new(&token_mint);
-    transaction_data.data_account = Pubkey::new(&data_account);
+    let parsed = payload::parse_cancel_stream(&encoded_str)?;
 
-    require!(
-        depositor_wallet_bytes == sender,
-        MessengerError::InvalidSenderWallet
-    );
+    transaction_data.sender = parsed.wallet.clone();
+    transaction_data.receiver = parsed.counterparty.clone();
+    transaction_data.cancel_by_receiver = parsed.by_receiver;
+    transaction_data.from_chain_id = from_chain_id as u64;
+    transaction_data.deadline = parsed.deadline;
+    transaction_data.token_mint = parsed.token_mint;
+    transaction_data.data_account = parsed.data_account;
+
+    // Settlement split: sender refund, receiver share.
+    transaction_data.refund_amount = normalize_amount(env, parsed.token_mint, parsed.refund)?;
+    transaction_data.amount = normalize_amount(env, parsed.token_mint, parsed.amount)?;
+
+    // The operation is stored under whoever signed the cancel.
+    let initiator = if parsed.by_receiver {
+        parsed.counterparty
+    } else {
+        parsed.wallet
+    };
+    require!(initiator == sender, MessengerError::InvalidSenderWallet);
+
+    emit_stream_action(transaction_data);
     Ok(())
 }
 
+fn emit_stream_action(transaction_data: &TransactionData) {
+    emit!(StreamActionParsed {
+        msg_type: transaction_data.msg_type,
+        sender: transaction_data.sender.clone(),
+        receiver: transaction_data.receiver.clone(),
+        token_mint: transaction_data.token_mint,
+        data_account: transaction_data.data_account,
+        amount: transaction_data.amount,
+        deadline: transaction_data.deadline
+    });
+}
+
 //sender will withdraw deposited token
 fn process_withdraw(
     encoded_str: Vec<u8>,
     from_chain_id: u16,
-    ctx: Context<StoreMsg>,
+    transaction_data: &mut TransactionData,
+    env: &MsgEnv,
     sender: Vec<u8>,
 ) -> Result<()> {
-    let transaction_data = &mut ctx.accounts.data_storage;
-    let amount = get_u64(encoded_str[1..9].to_vec());
-    let _to_chain_id = get_u256(encoded_str[9..41].to_vec());
-    let withdrawer_wallet_bytes = encoded_str[41..73].to_vec();
-    let token_mint = encoded_str[73..105].to_vec();
+    let parsed = payload::parse_withdraw(&encoded_str)?;
 
-    transaction_data.sender = withdrawer_wallet_bytes.clone();
+    transaction_data.sender = parsed.wallet.clone();
     transaction_data.from_chain_id = from_chain_id as u64;
-    transaction_data.token_mint = Pubkey::new(&token_mint);
-    transaction_data.amount = amount;
+    transaction_data.deadline = parsed.deadline;
+    transaction_data.token_mint = parsed.token_mint;
 
-    require!(
-        withdrawer_wallet_bytes == sender,
-        MessengerError::InvalidSenderWallet
-    );
+    transaction_data.amount = normalize_amount(env, parsed.token_mint, parsed.amount)?;
+    check_amount_plausibility(env, transaction_data.token_mint, transaction_data.amount)?;
+
+    require!(parsed.wallet == sender, MessengerError::InvalidSenderWallet);
+
+    emit!(WithdrawParsed {
+        sender: transaction_data.sender.clone(),
+        token_mint: transaction_data.token_mint,
+        amount: transaction_data.amount,
+        deadline: transaction_data.deadline
+    });
     Ok(())
 }
 
 fn process_instant_transfer(
     encoded_str: Vec<u8>,
     from_chain_id: u16,
-    ctx: Context<StoreMsg>,
+    transaction_data: &mut TransactionData,
+    env: &MsgEnv,
     sender: Vec<u8>,
 ) -> Result<()> {
-    let transaction_data = &mut ctx.accounts.data_storage;
-
-    let amount = get_u64(encoded_str[1..9].to_vec());
-    let _to_chain_id = get_u256(encoded_str[9..41].to_vec());
-    let senderwallet_bytes = encoded_str[41..73].to_vec();
-    let token_mint = encoded_str[73..105].to_vec();
-    let withdrawer_wallet_bytes = encoded_str[105..137].to_vec();
-
-    transaction_data.sender = senderwallet_bytes.clone();
-    transaction_data.receiver = withdrawer_wallet_bytes;
-    transaction_data.from_chain_id = from_chain_id as u64;
-    transaction_data.token_mint = Pubkey::new(&token_mint);
-    transaction_data.amount = amount;
-
-    require!(
-        senderwallet_bytes == sender,
-        MessengerError::InvalidSenderWallet
-    );
-    Ok(())
+    let parsed = payload::parse_instant_transfer(&encoded_str)?;
+    write_transfer(parsed, from_chain_id, transaction_data, env, sender)
 }
 
 fn process_direct_transfer(
     encoded_str: Vec<u8>,
     from_chain_id: u16,
-    ctx: Context<StoreMsg>,
+    transaction_data: &mut TransactionData,
+    env: &MsgEnv,
     sender: Vec<u8>,
 ) -> Result<()> {
-    let transaction_data = &mut ctx.accounts.data_storage;
+    let parsed = payload::parse_direct_transfer(&encoded_str)?;
+    write_transfer(parsed, from_chain_id, transaction_data, env, sender)
+}
 
-    let amount = get_u64(encoded_str[1..9].to_vec());
-    let _to_chain_id = get_u256(encoded_str[9..41].to_vec());
-    let senderwallet_bytes = encoded_str[41..73].to_vec();
-    let token_mint = encoded_str[73..105].to_vec();
-    let withdrawer_wallet_bytes = encoded_str[105..137].to_vec();
+fn write_transfer(
+    parsed: payload::TransferPayload,
+    from_chain_id: u16,
+    transaction_data: &mut TransactionData,
+    env: &MsgEnv,
+    sender: Vec<u8>,
+) -> Result<()> {
+    require!(
+        !env.config.reject_self_transfer || parsed.sender != parsed.receiver,
+        MessengerError::SelfTransfer
+    );
 
-    transaction_data.sender = senderwallet_bytes.clone();
-    transaction_data.receiver = withdrawer_wallet_bytes;
+    transaction_data.sender = parsed.sender.clone();
+    transaction_data.receiver = parsed.receiver;
     transaction_data.from_chain_id = from_chain_id as u64;
-    transaction_data.token_mint = Pubkey::new(&token_mint);
-    transaction_data.amount = amount;
+    transaction_data.deadline = parsed.deadline;
+    transaction_data.decimals = parsed.decimals;
+    transaction_data.token_mint = parsed.token_mint;
+
+    transaction_data.amount = normalize_amount(env, parsed.token_mint, parsed.amount)?;
+    check_amount_plausibility(env, transaction_data.token_mint, transaction_data.amount)?;
+    check_min_transfer_amount(env.config, transaction_data.amount)?;
+
+    require!(parsed.sender == sender, MessengerError::InvalidSenderWallet);
+
+    emit!(TransferParsed {
+        msg_type: transaction_data.msg_type,
+        sender: transaction_data.sender.clone(),
+        receiver: transaction_data.receiver.clone(),
+        token_mint: transaction_data.token_mint,
+        amount: transaction_data.amount,
+        deadline: transaction_data.deadline,
+        decimals: transaction_data.decimals
+    });
+    Ok(())
+}
+
+// Operations are identified by their DataStorage account, which every stage has at hand.
+fn emit_lifecycle(operation_id: Pubkey, stage: OperationStage, status: OperationStatus) {
+    emit!(OperationLifecycle {
+        operation_id: operation_id,
+        stage: stage,
+        status: status
+    });
+}
+
+// Refuses a transaction that already executed and marks it executed, the flag is
+// written back when the instruction returns. This is all it guarantees: the runtime
+// rejects re-entry into this program during the CPI, and a failing CPI aborts the
+// whole transaction, burn included, which leaves the transaction re-runnable.
+fn burn_transaction(transaction: &mut Account<Transaction>) -> Result<()> {
+    require!(!transaction.did_execute, MessengerError::AlreadyExecuted);
+    transaction.did_execute = true;
+    Ok(())
+}
+
+// invoke_signed only returns an error raised before the callee runs, e.g. an
+// account of the instruction still borrowed by this program; a failing callee aborts
+// the whole transaction and never gets here. Those are returned as InvalidCPI, with
+// the target program and the underlying error emitted first for relayers.
+fn check_cpi(
+    program_id: Pubkey,
+    operation_id: Pubkey,
+    result: std::result::Result<(), anchor_lang::prelude::ProgramError>,
+) -> Result<()> {
+    if let Err(err) = result {
+        emit!(cpi_failed(program_id, err));
+        emit_lifecycle(
+            operation_id,
+            OperationStage::Executed,
+            OperationStatus::Failed,
+        );
+        return Err(MessengerError::InvalidCPI.into());
+    }
+    Ok(())
+}
+
+// Keeps the callee's own error code, InvalidCPI alone doesn't say what went wrong.
+fn cpi_failed(program_id: Pubkey, err: anchor_lang::prelude::ProgramError) -> CpiFailed {
+    CpiFailed {
+        program_id: program_id,
+        error_code: u64::from(err),
+    }
+}
 
+// invoke_signed needs every account of the built instruction, fail here with a clear
+// error rather than inside the CPI.
+fn check_remaining_accounts(
+    transaction: &Transaction,
+    remaining_accounts: &[AccountInfo],
+) -> Result<()> {
     require!(
-        senderwallet_bytes == sender,
-        MessengerError::InvalidSenderWallet
+        remaining_accounts.len() >= transaction.accounts.len()
+            && transaction.accounts.iter().all(|acc| remaining_accounts
+                .iter()
+                .any(|info| info.key == &acc.pubkey)),
+        MessengerError::MissingRemainingAccounts
     );
     Ok(())
 }
 
 fn perform_cpi(
-    chain_id: Vec<u8>,
-    sender: [u8; 32],
-    transaction: Account<Transaction>,
-    pda_signer: UncheckedAccount,
+    chain_id: u64,
+    sender: &[u8],
+    transaction: &Transaction,
+    pda_signer: &UncheckedAccount,
     bumps: BTreeMap<String, u8>,
     remaining_accounts: &[AccountInfo],
 ) -> std::result::Result<(), anchor_lang::prelude::ProgramError> {
-    // Execute the transaction signed by the pdasender/pdareceiver.
-    let mut ix: Instruction = (transaction).deref().into();
+    // Execute the transaction signed by the pdasender/pdareceiver. The stored metas
+    // come from the client, so only the PDA and accounts that actually signed this
+    // instruction keep is_signer.
+    let mut ix: Instruction = transaction.into();
     ix.accounts = ix
         .accounts
         .iter()
         .map(|acc| {
             let mut acc = acc.clone();
-            if &acc.pubkey == pda_signer.key {
-                acc.is_signer = true;
-            }
+            acc.is_signer = &acc.pubkey == pda_signer.key
+                || (acc.is_signer
+                    && remaining_accounts
+                        .iter()
+                        .any(|info| info.key == &acc.pubkey && info.is_signer));
             acc
         })
         .collect();
 
     let bump = bumps.get("pda_signer").unwrap().to_le_bytes();
-    let seeds: &[&[_]] = &[&sender, &chain_id, bump.as_ref()];
-    let signer = &[&seeds[..]];
+    let chain_seed = chain_id_seed(chain_id);
+    let seeds: &[&[_]] = &[sender, chain_seed.as_bytes(), bump.as_ref()];
+    let signer = &[seeds];
     let accounts = remaining_accounts;
 
     solana_program::program::invoke_signed(&ix, accounts, signer)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::payload::tests::{code, error_of};
+
+    fn stored_key(prefix: &[u8], sender: &[u8; 32], count: u64) -> Pubkey {
+        Pubkey::find_program_address(&[prefix, sender, count.to_be_bytes().as_ref()], &ID).0
+    }
+
+    // Mirrors store_batched_msg for each message of a batch, then checks the
+    // accounts the create/execute contexts derive from the updated txn_count.
+    #[test]
+    fn batched_msgs_are_buildable() {
+        let sender = [7u8; 32];
+        let mut count = Count::default();
+        for _ in 0..MAX_STORE_MSG_BATCH_SIZE {
+            let current_count = next_count(&count).unwrap();
+            let data_store = stored_key(b"data_store", &sender, current_count);
+            let txn_status = stored_key(b"txn_status", &sender, current_count);
+            count.count = current_count;
+
+            assert_eq!(data_store, stored_key(b"data_store", &sender, count.count));
+            assert_eq!(txn_status, stored_key(b"txn_status", &sender, count.count));
+        }
+        assert_eq!(count.count, MAX_STORE_MSG_BATCH_SIZE as u64);
+    }
+
+    fn deposit_payload(sender: &[u8; 32], token_mint: Pubkey, amount: u64) -> Vec<u8> {
+        let mut bytes = vec![MessageCode::Deposit as u8];
+        bytes.extend_from_slice(&amount.to_be_bytes());
+        bytes.extend_from_slice(&[0u8; 24]);
+        bytes.extend_from_slice(&4u64.to_be_bytes());
+        bytes.extend_from_slice(sender);
+        bytes.extend_from_slice(token_mint.as_ref());
+        bytes.extend_from_slice(&0u64.to_be_bytes());
+        bytes
+    }
+
+    // Two pending deposits of one sender in different mints each get their own
+    // DataStorage, the second never overwriting the first.
+    #[test]
+    fn deposits_of_different_mints() {
+        let sender = [7u8; 32];
+        let (mint_a, mint_b) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let config = Config::default();
+        let env = MsgEnv {
+            config: &config,
+            remaining_accounts: &[],
+            source_decimals: 0,
+            native_chain_id: 4,
+        };
+
+        let mut count = Count::default();
+        let mut stored = Vec::new();
+        for (mint, amount) in [(mint_a, 100), (mint_b, 250)] {
+            let current_count = next_count(&count).unwrap();
+            let mut data_storage = TransactionData::default();
+            decode_payload(
+                deposit_payload(&sender, mint, amount),
+                4,
+                sender.to_vec(),
+                &mut data_storage,
+                &env,
+            )
+            .unwrap();
+            count.count = current_count;
+            stored.push((
+                stored_key(b"data_store", &sender, current_count),
+                data_storage,
+            ));
+        }
+
+        assert_ne!(stored[0].0, stored[1].0);
+        assert_eq!((stored[0].1.token_mint, stored[0].1.amount), (mint_a, 100));
+        assert_eq!((stored[1].1.token_mint, stored[1].1.amount), (mint_b, 250));
+    }
+
+    fn sample_vaa() -> MessageData {
+        MessageData {
+            vaa_version: 1,
+            consistency_level: 0x20,
+            vaa_time: 0x0102_0304,
+            nonce: 0x0506_0708,
+            sequence: 0x1112_1314_1516_1718,
+            emitter_chain: 0x0a0b,
+            emitter_address: [0xcc; 32],
+            payload: vec![0xde, 0xad],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn serialize_vaa_bytes() {
+        let mut expected = vec![0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x0a, 0x0b];
+        expected.extend_from_slice(&[0xcc; 32]);
+        expected.extend_from_slice(&[0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0x18]);
+        expected.push(0x20);
+        expected.extend_from_slice(&[0xde, 0xad]);
+
+        assert_eq!(serialize_vaa(&sample_vaa()).unwrap(), expected);
+    }
+
+    // A signed VAA is the header and signatures followed by the body serialize_vaa writes.
+    #[test]
+    fn parse_raw_vaa_body() {
+        let vaa = sample_vaa();
+        let mut raw = vec![1, 0, 0, 0, 2, 1];
+        raw.extend_from_slice(&[0u8; 66]);
+        raw.extend_from_slice(&serialize_vaa(&vaa).unwrap());
+
+        let parsed = parse_raw_vaa(&raw).unwrap();
+        assert_eq!(
+            serialize_vaa(&parsed).unwrap(),
+            serialize_vaa(&vaa).unwrap()
+        );
+        assert_eq!(parsed.vaa_version, 1);
+        assert!(parse_raw_vaa(&raw[..raw.len() - 3]).is_err());
+    }
+
+    fn u256_bytes(value: U256) -> Vec<u8> {
+        let mut bytes = vec![0u8; 32];
+        value.to_big_endian(&mut bytes);
+        bytes
+    }
+
+    #[test]
+    fn u256_bounded() {
+        let max = U256::from(u16::MAX);
+        for value in [U256::zero(), U256::from(4), max] {
+            assert_eq!(get_u256_bounded(u256_bytes(value), max).unwrap(), value);
+        }
+        for value in [max + 1, U256::MAX] {
+            assert!(get_u256_bounded(u256_bytes(value), max).is_err());
+        }
+    }
+
+    #[test]
+    fn next_count_overflow() {
+        assert!(next_count(&Count { count: u64::MAX }).is_err());
+        assert_eq!(next_count(&Count { count: 0 }).unwrap(), 1);
+    }
+
+    // Account data as an older layout left it: `data` cut to `stored_len` bytes,
+    // then zero padded to `space` the way grow_account does.
+    fn grown_data<T: AccountSerialize>(account: &T, stored_len: usize, space: usize) -> Vec<u8> {
+        let mut data = Vec::new();
+        account.try_serialize(&mut data).unwrap();
+        data.truncate(stored_len);
+        data.resize(space, 0);
+        data
+    }
+
+    // DataStorage as version 5 stored it, before the staged direct transfer fields.
+    #[test]
+    fn migrate_data_storage_from_v5() {
+        let stream = TransactionData {
+            sender: vec![1; 32],
+            receiver: vec![2; 32],
+            amount: 500,
+            msg_type: MessageCode::Stream as u8,
+            version: 5,
+            native_chain_id: 4,
+            staged_target_chain: 0xffff,
+            staged_fee: u64::MAX,
+            ..Default::default()
+        };
+        let mut stored = Vec::new();
+        stream.try_serialize(&mut stored).unwrap();
+        let mut data = grown_data(&stream, stored.len() - 10, DATA_STORAGE_SPACE);
+        let key = Pubkey::new_unique();
+        let mut lamports = 0;
+        let info = AccountInfo::new(&key, false, true, &mut lamports, &mut data, &ID, false, 0);
+
+        let data_account = Pubkey::new_unique();
+        assert_eq!(upgrade_data_storage(&info, Some(data_account)).unwrap(), 5);
+
+        let migrated = Account::<TransactionData>::try_from(&info).unwrap();
+        assert_eq!(migrated.version, DATA_STORAGE_VERSION);
+        assert_eq!(migrated.data_account, data_account);
+        assert_eq!(migrated.sender, stream.sender);
+        assert_eq!(migrated.amount, 500);
+        assert_eq!(migrated.native_chain_id, 4);
+        assert_eq!(migrated.staged_target_chain, 0);
+        assert_eq!(migrated.staged_fee, 0);
+
+        // A stream keeps the data account it was backfilled with.
+        assert!(upgrade_data_storage(&info, Some(Pubkey::new_unique())).is_err());
+    }
+
+    // Config as stored before min_transfer_amount and last_stats_day were appended.
+    #[test]
+    fn migrate_config_appended_field() {
+        let owner = Pubkey::new_unique();
+        let config = Config {
+            owner: owner,
+            nonce: 3,
+            registered_chains: vec![2, 4],
+            min_transfer_amount: u64::MAX,
+            last_stats_day: u64::MAX,
+            ..Default::default()
+        };
+        let mut stored = Vec::new();
+        config.try_serialize(&mut stored).unwrap();
+        let mut data = grown_data(&config, stored.len() - 16, CONFIG_SPACE);
+        assert!(data.starts_with(&Config::discriminator()));
+        let key = Pubkey::new_unique();
+        let mut lamports = 0;
+        let info = AccountInfo::new(&key, false, true, &mut lamports, &mut data, &ID, false, 0);
+
+        let migrated = Account::<Config>::try_from(&info).unwrap();
+        assert_eq!(migrated.owner, owner);
+        assert_eq!(migrated.nonce, 3);
+        assert_eq!(migrated.registered_chains, vec![2, 4]);
+        assert_eq!(migrated.min_transfer_amount, 0);
+        assert_eq!(migrated.last_stats_day, 0);
+    }
+
+    #[test]
+    fn open_transactions_limit() {
+        let mut config = Config {
+            max_open_transactions: 2,
+            ..Default::default()
+        };
+        open_transaction(&mut config).unwrap();
+        open_transaction(&mut config).unwrap();
+        assert_eq!(
+            error_of(open_transaction(&mut config)),
+            code(MessengerError::TooManyOpenTransactions)
+        );
+        assert_eq!(config.open_transactions, 2);
+
+        // Executing or cancelling one frees its slot for the next build.
+        close_transaction(&mut config);
+        open_transaction(&mut config).unwrap();
+        assert_eq!(config.open_transactions, 2);
+
+        close_transaction(&mut config);
+        close_transaction(&mut config);
+        close_transaction(&mut config);
+        assert_eq!(config.open_transactions, 0);
+
+        // 0 means unlimited.
+        config.max_open_transactions = 0;
+        config.open_transactions = u64::MAX - 1;
+        open_transaction(&mut config).unwrap();
+        assert_eq!(
+            error_of(open_transaction(&mut config)),
+            code(MessengerError::Overflow)
+        );
+    }
+
+    fn txn_status() -> TransactionStatus {
+        TransactionStatus {
+            executed: false,
+            staged: false,
+            created: false,
+        }
+    }
+
+    // What the create_transaction_* instructions check and set.
+    #[test]
+    fn create_twice() {
+        let mut status = txn_status();
+        check_creatable(&status).unwrap();
+        status.created = true;
+        assert_eq!(
+            error_of(check_creatable(&status)),
+            code(MessengerError::AlreadyCreated)
+        );
+
+        let executed = TransactionStatus {
+            executed: true,
+            ..txn_status()
+        };
+        assert_eq!(
+            error_of(check_creatable(&executed)),
+            code(MessengerError::AlreadyExecuted)
+        );
+    }
+
+    // execute_transaction checks the operation, then burns the built transaction.
+    #[test]
+    fn execute_twice() {
+        let mut status = txn_status();
+        check_not_executed(&status).unwrap();
+        status.executed = true;
+        assert_eq!(
+            error_of(check_not_executed(&status)),
+            code(MessengerError::AlreadyExecuted)
+        );
+
+        let transaction = Transaction {
+            program_id: ID,
+            accounts: Vec::new(),
+            data: Vec::new(),
+            did_execute: false,
+            payer: Pubkey::new_unique(),
+            data_storage: Pubkey::new_unique(),
+        };
+        let mut data = Vec::new();
+        transaction.try_serialize(&mut data).unwrap();
+        let key = Pubkey::new_unique();
+        let mut lamports = 0;
+        let info = AccountInfo::new(&key, false, true, &mut lamports, &mut data, &ID, false, 0);
+        let mut transaction = Account::<Transaction>::try_from(&info).unwrap();
+        burn_transaction(&mut transaction).unwrap();
+        assert_eq!(
+            error_of(burn_transaction(&mut transaction)),
+            code(MessengerError::AlreadyExecuted)
+        );
+    }
+
+    #[test]
+    fn checked_math_bounds() {
+        assert_eq!(checked_add(u64::MAX - 1, 1).unwrap(), u64::MAX);
+        assert_eq!(
+            error_of(checked_add(u64::MAX, 1)),
+            code(MessengerError::MathOverflow)
+        );
+        assert_eq!(checked_sub(0, 0).unwrap(), 0);
+        assert_eq!(checked_sub(u64::MAX, u64::MAX).unwrap(), 0);
+        assert_eq!(
+            error_of(checked_sub(0, 1)),
+            code(MessengerError::MathOverflow)
+        );
+        assert_eq!(checked_mul(u64::MAX, 1).unwrap(), u64::MAX);
+        assert_eq!(
+            error_of(checked_mul(u64::MAX / 10 + 1, 10)),
+            code(MessengerError::MathOverflow)
+        );
+        assert_eq!(checked_pow10(19).unwrap(), 10_000_000_000_000_000_000);
+        assert_eq!(
+            error_of(checked_pow10(20)),
+            code(MessengerError::MathOverflow)
+        );
+    }
+
+    #[test]
+    fn checked_fee_bounds() {
+        assert_eq!(checked_fee(0, 10_000).unwrap(), 0);
+        assert_eq!(checked_fee(u64::MAX, 0).unwrap(), 0);
+        // Rounded up, the whole amount at 100%, no wrap on u64::MAX * bps.
+        assert_eq!(checked_fee(1, 1).unwrap(), 1);
+        assert_eq!(checked_fee(u64::MAX, 10_000).unwrap(), u64::MAX);
+        assert_eq!(checked_fee(u64::MAX, 5_000).unwrap(), u64::MAX / 2 + 1);
+        assert_eq!(
+            error_of(checked_fee(1, 10_001)),
+            code(MessengerError::MathOverflow)
+        );
+    }
+
+    fn transfer_config(owner: Pubkey, relayer: Pubkey) -> Config {
+        Config {
+            owner: owner,
+            relayer_authority: relayer,
+            target_chains: vec![2, 4],
+            ..Default::default()
+        }
+    }
+
+    // What a one phase direct transfer checks before bridging.
+    #[test]
+    fn bridge_sysvars() {
+        use anchor_lang::solana_program::sysvar::{clock, rent};
+
+        check_bridge_sysvars(clock::ID, rent::ID).unwrap();
+        assert_eq!(
+            error_of(check_bridge_sysvars(Pubkey::new_unique(), rent::ID)),
+            code(MessengerError::InvalidClockAccount)
+        );
+        // The rent sysvar can't stand in for the clock, nor the other way round.
+        assert_eq!(
+            error_of(check_bridge_sysvars(rent::ID, rent::ID)),
+            code(MessengerError::InvalidClockAccount)
+        );
+        assert_eq!(
+            error_of(check_bridge_sysvars(clock::ID, clock::ID)),
+            code(MessengerError::InvalidRentAccount)
+        );
+    }
+
+    #[test]
+    fn direct_transfer_params() {
+        let (owner, relayer) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let config = transfer_config(owner, relayer);
+
+        check_transfer_params(&config, owner, 2, 10, 100).unwrap();
+        check_transfer_params(&config, relayer, 4, 99, 100).unwrap();
+        assert_eq!(
+            error_of(check_transfer_params(
+                &config,
+                Pubkey::new_unique(),
+                2,
+                10,
+                100
+            )),
+            code(MessengerError::InvalidCaller)
+        );
+        for target_chain in [0, CHAIN_ID_SOLANA, 6] {
+            assert_eq!(
+                error_of(check_transfer_params(
+                    &config,
+                    relayer,
+                    target_chain,
+                    10,
+                    100
+                )),
+                code(MessengerError::UnsupportedTargetChain)
+            );
+        }
+        assert_eq!(
+            error_of(check_transfer_params(&config, relayer, 2, 100, 100)),
+            code(MessengerError::FeeConsumesAmount)
+        );
+        assert_eq!(
+            error_of(check_transfer_params(&config, relayer, 2, 101, 100)),
+            code(MessengerError::MathOverflow)
+        );
+    }
+
+    // A receiver-withdraw may be signed by the stream's receiver itself, any other
+    // wallet still has to be the owner or relayer.
+    #[test]
+    fn receiver_signed_withdraw() {
+        let (owner, relayer) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let config = transfer_config(owner, relayer);
+        let receiver = Pubkey::new_unique();
+        let data_storage = TransactionData {
+            sender: vec![9; 32],
+            receiver: receiver.to_bytes().to_vec(),
+            ..Default::default()
+        };
+
+        check_withdraw_caller(&config, receiver, &data_storage).unwrap();
+        check_withdraw_caller(&config, owner, &data_storage).unwrap();
+        check_withdraw_caller(&config, relayer, &data_storage).unwrap();
+        assert_eq!(
+            error_of(check_withdraw_caller(
+                &config,
+                Pubkey::new_unique(),
+                &data_storage
+            )),
+            code(MessengerError::InvalidCaller)
+        );
+        // The receiver is only trusted for its own stream, never on other paths.
+        assert_eq!(
+            error_of(check_caller(&config, receiver)),
+            code(MessengerError::InvalidCaller)
+        );
+        // Without a relayer set, the default key doesn't pass as one.
+        let no_relayer = transfer_config(owner, Pubkey::default());
+        assert_eq!(
+            error_of(check_withdraw_caller(
+                &no_relayer,
+                Pubkey::default(),
+                &data_storage
+            )),
+            code(MessengerError::InvalidCaller)
+        );
+    }
+
+    // Staging is held to the same checks, and leaves the operation untouched when
+    // they fail.
+    #[test]
+    fn stage_direct_transfer_checks() {
+        let (owner, relayer) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let config = transfer_config(owner, relayer);
+        let operation_id = Pubkey::new_unique();
+        let mut data_storage = TransactionData {
+            amount: 100,
+            msg_type: MessageCode::DirectTransfer as u8,
+            ..Default::default()
+        };
+        let mut txn_status = TransactionStatus {
+            executed: false,
+            staged: false,
+            created: false,
+        };
+
+        for (caller, target_chain, fee) in [
+            (Pubkey::new_unique(), 2, 10),
+            (relayer, CHAIN_ID_SOLANA, 10),
+            (relayer, 2, 101),
+        ] {
+            assert!(stage_direct_transfer(
+                operation_id,
+                &config,
+                caller,
+                &mut data_storage,
+                &mut txn_status,
+                [1; 32],
+                1,
+                target_chain,
+                fee,
+            )
+            .is_err());
+            assert!(!txn_status.staged);
+            assert_eq!(data_storage.staged_target_chain, 0);
+            assert_eq!(data_storage.staged_fee, 0);
+        }
+
+        stage_direct_transfer(
+            operation_id,
+            &config,
+            relayer,
+            &mut data_storage,
+            &mut txn_status,
+            [1; 32],
+            1,
+            4,
+            99,
+        )
+        .unwrap();
+        assert!(txn_status.staged);
+        assert_eq!(data_storage.staged_target_chain, 4);
+        assert_eq!(data_storage.staged_fee, 99);
+    }
+
+    // Token-2022 mint data carrying only a TransferFeeConfig, with the older and
+    // newer (epoch, maximum_fee, basis_points).
+    fn fee_mint(older: (u64, u64, u16), newer: (u64, u64, u16)) -> Vec<u8> {
+        let mut data = vec![0u8; TOKEN_2022_EXTENSIONS_OFFSET - 1];
+        data.push(1);
+        data.extend_from_slice(&TRANSFER_FEE_CONFIG_EXTENSION.to_le_bytes());
+        data.extend_from_slice(&(TRANSFER_FEE_CONFIG_LEN as u16).to_le_bytes());
+        data.extend_from_slice(&[0u8; 72]);
+        for (epoch, maximum_fee, basis_points) in [older, newer] {
+            data.extend_from_slice(&epoch.to_le_bytes());
+            data.extend_from_slice(&maximum_fee.to_le_bytes());
+            data.extend_from_slice(&basis_points.to_le_bytes());
+        }
+        data
+    }
+
+    fn received(mut data: Vec<u8>, owner: &Pubkey, amount: u64, epoch: u64) -> Result<u64> {
+        let key = Pubkey::new_unique();
+        let mut lamports = 0;
+        let mint = AccountInfo::new(
+            &key,
+            false,
+            false,
+            &mut lamports,
+            &mut data,
+            owner,
+            false,
+            0,
+        );
+        post_fee_amount(&mint, amount, epoch)
+    }
+
+    // wSOL and most SPL mints have 9 decimals, one more than the bridge carries.
+    #[test]
+    fn dust_of_nine_decimals() {
+        let mint = Pubkey::new_unique();
+        assert_eq!(bridge_dust(1_234_567_891, 9), 1);
+        assert_eq!(bridge_dust(1_234_567_899, 9), 9);
+        assert_eq!(bridge_dust(1_234_567_890, 9), 0);
+        assert_eq!(bridge_dust(1_234_567_891, BRIDGE_MAX_DECIMALS), 0);
+
+        let event = dust_truncated(mint, bridge_dust(1_234_567_899, 9)).unwrap();
+        assert_eq!((event.mint, event.lost_amount), (mint, 9));
+        assert!(dust_truncated(mint, bridge_dust(1_234_567_890, 9)).is_none());
+    }
+
+    #[test]
+    fn failed_cpi_reported() {
+        use anchor_lang::prelude::ProgramError;
+
+        let (program_id, operation_id) = (Pubkey::new_unique(), Pubkey::new_unique());
+        check_cpi(program_id, operation_id, Ok(())).unwrap();
+        assert_eq!(
+            error_of(check_cpi(
+                program_id,
+                operation_id,
+                Err(ProgramError::Custom(6003))
+            )),
+            code(MessengerError::InvalidCPI)
+        );
+
+        let event = cpi_failed(program_id, ProgramError::Custom(6003));
+        assert_eq!((event.program_id, event.error_code), (program_id, 6003));
+        // Builtin errors keep their own code rather than collapsing to 0.
+        let event = cpi_failed(program_id, ProgramError::InsufficientFunds);
+        assert_ne!(event.error_code, 0);
+    }
+
+    #[test]
+    fn transfer_fee_mint() {
+        let one_percent = fee_mint((0, u64::MAX, 100), (0, u64::MAX, 100));
+        let id = &TOKEN_2022_PROGRAM_ID;
+        assert_eq!(received(one_percent.clone(), id, 1_000, 0).unwrap(), 990);
+        // Rounded up like Token-2022 does.
+        assert_eq!(received(one_percent.clone(), id, 1_001, 0).unwrap(), 990);
+        // Other token programs charge nothing, whatever the data holds.
+        let spl_token = &anchor_spl::token::ID;
+        assert_eq!(received(one_percent, spl_token, 1_000, 0).unwrap(), 1_000);
+
+        let capped = fee_mint((0, 5, 100), (0, 5, 100));
+        assert_eq!(received(capped, id, 1_000, 0).unwrap(), 995);
+
+        // The newer fee only applies from its epoch on.
+        let raised = fee_mint((0, u64::MAX, 0), (10, u64::MAX, 10_000));
+        assert_eq!(received(raised.clone(), id, 1_000, 9).unwrap(), 1_000);
+        assert_eq!(
+            error_of(received(raised, id, 1_000, 10)),
+            code(MessengerError::FeeConsumesAmount)
+        );
+
+        let mut no_extension = vec![0u8; TOKEN_2022_EXTENSIONS_OFFSET - 1];
+        no_extension.push(1);
+        assert_eq!(received(no_extension, id, 1_000, 0).unwrap(), 1_000);
+    }
+
+    // A counter left too high is moved back and the next message picks up after it,
+    // unless that message would land on an operation already stored.
+    #[test]
+    fn reset_then_increment() {
+        let key = Pubkey::new_unique();
+        let mut lamports = 0;
+        let mut no_data = Vec::new();
+        let next_free = AccountInfo::new(
+            &key,
+            false,
+            false,
+            &mut lamports,
+            &mut no_data,
+            &solana_program::system_program::ID,
+            false,
+            0,
+        );
+        let mut count = Count { count: 1_000 };
+        assert_eq!(reset_count(&mut count, 4, &next_free).unwrap(), 1_000);
+        assert_eq!(next_count(&count).unwrap(), 5);
+        count.count = next_count(&count).unwrap();
+        assert_eq!(reset_count(&mut count, 9, &next_free).unwrap(), 5);
+        assert_eq!(next_count(&count).unwrap(), 10);
+
+        let mut stored = vec![0u8; DATA_STORAGE_SPACE];
+        let mut lamports = 1;
+        let next_stored = AccountInfo::new(
+            &key,
+            false,
+            false,
+            &mut lamports,
+            &mut stored,
+            &ID,
+            false,
+            0,
+        );
+        assert_eq!(
+            error_of(reset_count(&mut count, 2, &next_stored)),
+            code(MessengerError::CountMismatch)
+        );
+        assert_eq!(count.count, 9);
+    }
+
+    // Messages of one day accumulate in its DailyStats, the first message of the
+    // next day rolls them up and starts over in the account of that day.
+    #[test]
+    fn daily_stats_roll_over() {
+        let day = 19_000;
+        let (mint_a, mint_b) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut config = Config::default();
+        let mut today = DailyStats::default();
+
+        let key = Pubkey::new_unique();
+        let mut lamports = 0;
+        let mut no_data = Vec::new();
+        let never_used = AccountInfo::new(
+            &key,
+            false,
+            false,
+            &mut lamports,
+            &mut no_data,
+            &solana_program::system_program::ID,
+            false,
+            0,
+        );
+        roll_over_daily_stats(&mut config, &never_used, day).unwrap();
+        assert_eq!(config.last_stats_day, day);
+
+        record_daily_stats(&mut today, day, mint_a, 100);
+        record_daily_stats(&mut today, day, mint_b, 5);
+        record_daily_stats(&mut today, day, mint_a, 20);
+        assert_eq!(today.count, 3);
+        assert_eq!(today.mints.len(), 2);
+        assert_eq!(today.mints[0].count, 2);
+        assert_eq!(today.mints[0].volume, 120);
+
+        let mut data = Vec::new();
+        today.try_serialize(&mut data).unwrap();
+        let info = AccountInfo::new(&key, false, false, &mut lamports, &mut data, &ID, false, 0);
+
+        // Same day, nothing to roll up.
+        roll_over_daily_stats(&mut config, &info, day).unwrap();
+        assert_eq!(config.last_stats_day, day);
+
+        roll_over_daily_stats(&mut config, &info, day + 1).unwrap();
+        assert_eq!(config.last_stats_day, day + 1);
+        let rollup = daily_rollup(&info).unwrap().unwrap();
+        assert_eq!((rollup.day, rollup.count, rollup.volume), (day, 3, 125));
+
+        let mut tomorrow = DailyStats::default();
+        record_daily_stats(&mut tomorrow, day + 1, mint_b, 7);
+        assert_eq!((tomorrow.day, tomorrow.count), (day + 1, 1));
+        assert_eq!(tomorrow.mints[0].volume, 7);
+    }
+
+    // txn_count as stored while the count was a u8.
+    #[test]
+    fn migrate_txn_count_from_u8() {
+        let mut data = Count::discriminator().to_vec();
+        data.push(200);
+        data.resize(TXN_COUNT_SPACE, 0);
+        let key = Pubkey::new_unique();
+        let mut lamports = 0;
+        let info = AccountInfo::new(&key, false, true, &mut lamports, &mut data, &ID, false, 0);
+
+        assert_eq!(Account::<Count>::try_from(&info).unwrap().count, 200);
+    }
+}
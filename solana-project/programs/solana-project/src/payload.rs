@@ -0,0 +1,704 @@
+use anchor_lang::prelude::*;
+
+use primitive_types::U256;
+
+use crate::constants::*;
+use crate::errors::MessengerError;
+use crate::{
+    get_deadline, get_optional_u64, get_pubkey, get_u256_bounded, get_u64, RelayInstruction,
+};
+
+// Wire format of a VAA payload. V1 payloads start with the opcode, as every emitter
+// encodes them today. Later versions are marked by PAYLOAD_VERSION_PREFIX followed
+// by the version byte and the declared sender length, then the opcode and the layout
+// of that version.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PayloadVersion {
+    V1,
+    // Reserved for u128 amounts and the cliff in the stream layout, not decoded yet.
+    V2,
+}
+
+// Split a payload into its version and the body that starts with the opcode.
+pub fn split_version(payload: &[u8]) -> Result<(PayloadVersion, &[u8])> {
+    match payload {
+        [] => Err(MessengerError::InvalidPayload.into()),
+        [PAYLOAD_VERSION_PREFIX, version, body @ ..] => {
+            let version = match *version {
+                2 => PayloadVersion::V2,
+                _ => return Err(MessengerError::UnsupportedPayloadVersion.into()),
+            };
+            let (sender_len, body) = body.split_first().ok_or(MessengerError::InvalidPayload)?;
+            check_sender_len(*sender_len)?;
+            require!(!body.is_empty(), MessengerError::InvalidPayload);
+            Ok((version, body))
+        }
+        [PAYLOAD_VERSION_PREFIX] => Err(MessengerError::InvalidPayload.into()),
+        body => Ok((PayloadVersion::V1, body)),
+    }
+}
+
+// The layouts slice a SENDER_LEN byte sender, a payload declaring another length
+// was encoded for a different layout.
+pub fn check_sender_len(declared: u8) -> Result<()> {
+    require!(
+        declared as usize == SENDER_LEN,
+        MessengerError::SenderLengthMismatch
+    );
+    Ok(())
+}
+
+// Where a payload stopped matching its layout. Logged right before the error is
+// returned, so a diverging EVM encoder can be traced to the field.
+pub struct PayloadError {
+    pub opcode: u8,
+    pub offset: usize,
+    pub expected: usize,
+    pub got: usize,
+}
+
+impl PayloadError {
+    pub fn log(&self) {
+        msg!(
+            "PayloadError: opcode {} offset {} expected {} got {}",
+            self.opcode,
+            self.offset,
+            self.expected,
+            self.got
+        );
+    }
+}
+
+// Length of the fixed part of each layout, the optional trailing fields excluded.
+// Relay only has its sender fixed, the instruction after it is checked on decode.
+pub fn fixed_len(code: MessageCode) -> usize {
+    match code {
+        MessageCode::Deposit | MessageCode::Withdraw => 105,
+        MessageCode::InstantTransfer | MessageCode::DirectTransfer => 137,
+        MessageCode::Pause | MessageCode::WithdrawStream | MessageCode::CancelStream => 161,
+        MessageCode::Stream => 169,
+        MessageCode::StreamWithCliff => 177,
+        MessageCode::UpdateStream => 185,
+        MessageCode::Relay => 33,
+    }
+}
+
+// A v1 body, opcode included, too short for its layout would otherwise panic on the
+// first slice past its end.
+pub fn check_len(code: MessageCode, body: &[u8]) -> Result<()> {
+    let expected = fixed_len(code);
+    if body.len() < expected {
+        PayloadError {
+            opcode: code as u8,
+            offset: body.len(),
+            expected: expected,
+            got: body.len(),
+        }
+        .log();
+        return Err(MessengerError::InvalidPayload.into());
+    }
+    Ok(())
+}
+
+// Decoded payload fields, before the writer checks them against the VAA and the
+// config. Parsing only depends on the bytes, so the layouts can be exercised
+// without accounts.
+pub struct AmountPayload {
+    pub amount: u64,
+    pub wallet: Vec<u8>,
+    pub token_mint: Pubkey,
+    pub deadline: u64,
+}
+
+pub struct StreamPayload {
+    pub start_time: u64,
+    pub end_time: u64,
+    pub amount: u64,
+    pub sender: Vec<u8>,
+    pub receiver: Vec<u8>,
+    pub can_update: bool,
+    pub can_cancel: bool,
+    pub token_mint: Pubkey,
+    // 0 for the plain Stream layout.
+    pub cliff_time: u64,
+    pub deadline: u64,
+}
+
+pub struct StreamUpdatePayload {
+    pub start_time: u64,
+    pub end_time: u64,
+    pub amount: u64,
+    pub sender: Vec<u8>,
+    pub receiver: Vec<u8>,
+    pub token_mint: Pubkey,
+    pub data_account: Pubkey,
+    pub deadline: u64,
+}
+
+// wallet is the one signing on the source chain: the depositor for pause and
+// cancel, the withdrawer for withdraw_stream.
+pub struct StreamActionPayload {
+    pub wallet: Vec<u8>,
+    pub token_mint: Pubkey,
+    pub counterparty: Vec<u8>,
+    pub data_account: Pubkey,
+    pub deadline: u64,
+    // Optional trailing amounts, 0 when missing: the withdrawn amount or the
+    // receiver settlement, and the sender refund of a cancel.
+    pub amount: u64,
+    pub refund: u64,
+    // Cancel only, optional after the split: the counterparty, i.e. the receiver,
+    // signed the cancel.
+    pub by_receiver: bool,
+}
+
+pub struct TransferPayload {
+    pub amount: u64,
+    pub sender: Vec<u8>,
+    pub token_mint: Pubkey,
+    pub receiver: Vec<u8>,
+    pub deadline: u64,
+    // Direct transfers only.
+    pub decimals: Option<u8>,
+}
+
+pub struct RelayPayload {
+    pub sender: Vec<u8>,
+    pub instruction: RelayInstruction,
+    pub instruction_bytes: Vec<u8>,
+}
+
+fn get_chain_id(bytes: &[u8]) -> Result<U256> {
+    get_u256_bounded(bytes.to_vec(), U256::from(u16::MAX))
+}
+
+// Deposit and Withdraw share their layout.
+fn parse_amount(code: MessageCode, bytes: &[u8]) -> Result<AmountPayload> {
+    check_len(code, bytes)?;
+    let amount = get_u64(bytes[1..9].to_vec());
+    let _to_chain_id = get_chain_id(&bytes[9..41])?;
+    require!(amount > 0, MessengerError::ZeroAmount);
+
+    Ok(AmountPayload {
+        amount: amount,
+        wallet: bytes[41..73].to_vec(),
+        token_mint: get_pubkey(&bytes[73..105])?,
+        deadline: get_deadline(bytes, 105),
+    })
+}
+
+pub fn parse_deposit(bytes: &[u8]) -> Result<AmountPayload> {
+    parse_amount(MessageCode::Deposit, bytes)
+}
+
+pub fn parse_withdraw(bytes: &[u8]) -> Result<AmountPayload> {
+    parse_amount(MessageCode::Withdraw, bytes)
+}
+
+// StreamWithCliff is the Stream layout with the cliff before the deadline.
+fn parse_stream_layout(code: MessageCode, bytes: &[u8]) -> Result<StreamPayload> {
+    check_len(code, bytes)?;
+    let start_time = get_u64(bytes[1..9].to_vec());
+    let end_time = get_u64(bytes[9..17].to_vec());
+    let amount = get_u64(bytes[17..25].to_vec());
+    let _to_chain_id = get_chain_id(&bytes[25..57])?;
+
+    require!(start_time < end_time, MessengerError::InvalidTimeRange);
+    require!(amount > 0, MessengerError::ZeroAmount);
+
+    let (cliff_time, deadline_offset) = match code {
+        MessageCode::StreamWithCliff => (get_u64(bytes[169..177].to_vec()), 177),
+        _ => (0, 169),
+    };
+    if code == MessageCode::StreamWithCliff {
+        require!(
+            start_time <= cliff_time && cliff_time <= end_time,
+            MessengerError::InvalidCliffTime
+        );
+    }
+
+    Ok(StreamPayload {
+        start_time: start_time,
+        end_time: end_time,
+        amount: amount,
+        sender: bytes[57..89].to_vec(),
+        receiver: bytes[89..121].to_vec(),
+        can_update: get_u64(bytes[121..129].to_vec()) == 1,
+        can_cancel: get_u64(bytes[129..137].to_vec()) == 1,
+        token_mint: get_pubkey(&bytes[137..169])?,
+        cliff_time: cliff_time,
+        deadline: get_deadline(bytes, deadline_offset),
+    })
+}
+
+pub fn parse_stream(bytes: &[u8]) -> Result<StreamPayload> {
+    parse_stream_layout(MessageCode::Stream, bytes)
+}
+
+pub fn parse_stream_with_cliff(bytes: &[u8]) -> Result<StreamPayload> {
+    parse_stream_layout(MessageCode::StreamWithCliff, bytes)
+}
+
+pub fn parse_update_stream(bytes: &[u8]) -> Result<StreamUpdatePayload> {
+    check_len(MessageCode::UpdateStream, bytes)?;
+    let start_time = get_u64(bytes[1..9].to_vec());
+    let end_time = get_u64(bytes[9..17].to_vec());
+    let amount = get_u64(bytes[17..25].to_vec());
+    let _to_chain_id = get_chain_id(&bytes[25..57])?;
+
+    require!(start_time < end_time, MessengerError::InvalidTimeRange);
+    require!(amount > 0, MessengerError::ZeroAmount);
+
+    Ok(StreamUpdatePayload {
+        start_time: start_time,
+        end_time: end_time,
+        amount: amount,
+        sender: bytes[57..89].to_vec(),
+        receiver: bytes[89..121].to_vec(),
+        token_mint: get_pubkey(&bytes[121..153])?,
+        data_account: get_pubkey(&bytes[153..185])?,
+        deadline: get_deadline(bytes, 185),
+    })
+}
+
+// Pause, WithdrawStream and CancelStream share their layout up to the deadline.
+fn parse_stream_action(code: MessageCode, bytes: &[u8]) -> Result<StreamActionPayload> {
+    check_len(code, bytes)?;
+    let _to_chain_id = get_chain_id(&bytes[1..33])?;
+
+    let (amount, refund) = match code {
+        MessageCode::WithdrawStream => (get_optional_u64(bytes, 169), 0),
+        MessageCode::CancelStream => (get_optional_u64(bytes, 177), get_optional_u64(bytes, 169)),
+        _ => (0, 0),
+    };
+    let by_receiver = code == MessageCode::CancelStream && get_optional_u64(bytes, 185) == 1;
+
+    Ok(StreamActionPayload {
+        wallet: bytes[33..65].to_vec(),
+        token_mint: get_pubkey(&bytes[65..97])?,
+        counterparty: bytes[97..129].to_vec(),
+        data_account: get_pubkey(&bytes[129..161])?,
+        deadline: get_deadline(bytes, 161),
+        amount: amount,
+        refund: refund,
+        by_receiver: by_receiver,
+    })
+}
+
+pub fn parse_pause(bytes: &[u8]) -> Result<StreamActionPayload> {
+    parse_stream_action(MessageCode::Pause, bytes)
+}
+
+pub fn parse_withdraw_stream(bytes: &[u8]) -> Result<StreamActionPayload> {
+    parse_stream_action(MessageCode::WithdrawStream, bytes)
+}
+
+pub fn parse_cancel_stream(bytes: &[u8]) -> Result<StreamActionPayload> {
+    parse_stream_action(MessageCode::CancelStream, bytes)
+}
+
+// InstantTransfer and DirectTransfer share their layout, direct ones may carry
+// the decimals after the deadline.
+fn parse_transfer(code: MessageCode, bytes: &[u8]) -> Result<TransferPayload> {
+    check_len(code, bytes)?;
+    let amount = get_u64(bytes[1..9].to_vec());
+    let _to_chain_id = get_chain_id(&bytes[9..41])?;
+    require!(amount > 0, MessengerError::ZeroAmount);
+
+    let decimals = match code {
+        MessageCode::DirectTransfer => bytes.get(145).copied(),
+        _ => None,
+    };
+
+    Ok(TransferPayload {
+        amount: amount,
+        sender: bytes[41..73].to_vec(),
+        token_mint: get_pubkey(&bytes[73..105])?,
+        receiver: bytes[105..137].to_vec(),
+        deadline: get_deadline(bytes, 137),
+        decimals: decimals,
+    })
+}
+
+pub fn parse_instant_transfer(bytes: &[u8]) -> Result<TransferPayload> {
+    parse_transfer(MessageCode::InstantTransfer, bytes)
+}
+
+pub fn parse_direct_transfer(bytes: &[u8]) -> Result<TransferPayload> {
+    parse_transfer(MessageCode::DirectTransfer, bytes)
+}
+
+pub fn parse_relay(bytes: &[u8]) -> Result<RelayPayload> {
+    check_len(MessageCode::Relay, bytes)?;
+    let instruction_bytes = &bytes[33..];
+    let instruction = RelayInstruction::try_from_slice(instruction_bytes).map_err(|_| {
+        PayloadError {
+            opcode: MessageCode::Relay as u8,
+            offset: 33,
+            expected: 33 + 32 + 4 + 4,
+            got: bytes.len(),
+        }
+        .log();
+        MessengerError::InvalidPayload
+    })?;
+
+    Ok(RelayPayload {
+        sender: bytes[1..33].to_vec(),
+        instruction: instruction,
+        instruction_bytes: instruction_bytes.to_vec(),
+    })
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    fn error_code(err: anchor_lang::error::Error) -> u32 {
+        match err {
+            anchor_lang::error::Error::AnchorError(err) => err.error_code_number,
+            anchor_lang::error::Error::ProgramError(err) => {
+                panic!("unexpected program error {:?}", err)
+            }
+        }
+    }
+
+    pub(crate) fn error_of<T>(result: Result<T>) -> u32 {
+        match result {
+            Ok(_) => panic!("expected an error"),
+            Err(err) => error_code(err),
+        }
+    }
+
+    pub(crate) fn code(err: MessengerError) -> u32 {
+        err as u32 + anchor_lang::error::ERROR_CODE_OFFSET
+    }
+
+    type Parser<T> = fn(&[u8]) -> Result<T>;
+
+    fn be(value: u64) -> Vec<u8> {
+        value.to_be_bytes().to_vec()
+    }
+
+    fn chain(chain_id: u64) -> Vec<u8> {
+        let mut bytes = vec![0u8; 24];
+        bytes.extend_from_slice(&chain_id.to_be_bytes());
+        bytes
+    }
+
+    fn key(byte: u8) -> Pubkey {
+        Pubkey::new_from_array([byte; 32])
+    }
+
+    fn payload(code: MessageCode, fields: &[Vec<u8>]) -> Vec<u8> {
+        let mut bytes = vec![code as u8];
+        for field in fields {
+            bytes.extend_from_slice(field);
+        }
+        bytes
+    }
+
+    fn stream_fields(cliff: Option<u64>) -> Vec<Vec<u8>> {
+        let mut fields = vec![
+            be(100),
+            be(200),
+            be(5_000),
+            chain(4),
+            vec![1; 32],
+            vec![2; 32],
+            be(1),
+            be(0),
+            vec![3; 32],
+        ];
+        fields.extend(cliff.map(be));
+        fields.push(be(999));
+        fields
+    }
+
+    fn action_fields(extra: &[u64]) -> Vec<Vec<u8>> {
+        let mut fields = vec![
+            chain(4),
+            vec![1; 32],
+            vec![3; 32],
+            vec![2; 32],
+            vec![4; 32],
+            be(999),
+        ];
+        fields.extend(extra.iter().map(|value| be(*value)));
+        fields
+    }
+
+    #[test]
+    fn amount_layout() {
+        let fields = [be(5_000), chain(4), vec![1; 32], vec![3; 32], be(999)];
+        let parsers: [(MessageCode, Parser<AmountPayload>); 2] = [
+            (MessageCode::Deposit, parse_deposit),
+            (MessageCode::Withdraw, parse_withdraw),
+        ];
+        for (opcode, parse) in parsers {
+            let parsed = parse(&payload(opcode, &fields)).unwrap();
+            assert_eq!(parsed.amount, 5_000);
+            assert_eq!(parsed.wallet, vec![1; 32]);
+            assert_eq!(parsed.token_mint, key(3));
+            assert_eq!(parsed.deadline, 999);
+        }
+
+        // The deadline is optional.
+        let parsed = parse_deposit(&payload(MessageCode::Deposit, &fields[..4])).unwrap();
+        assert_eq!(parsed.deadline, 0);
+    }
+
+    #[test]
+    fn stream_layout() {
+        let parsed = parse_stream(&payload(MessageCode::Stream, &stream_fields(None))).unwrap();
+        assert_eq!(
+            (parsed.start_time, parsed.end_time, parsed.amount),
+            (100, 200, 5_000)
+        );
+        assert_eq!(parsed.sender, vec![1; 32]);
+        assert_eq!(parsed.receiver, vec![2; 32]);
+        assert!(parsed.can_update && !parsed.can_cancel);
+        assert_eq!(parsed.token_mint, key(3));
+        assert_eq!((parsed.cliff_time, parsed.deadline), (0, 999));
+
+        let bytes = payload(MessageCode::StreamWithCliff, &stream_fields(Some(150)));
+        let parsed = parse_stream_with_cliff(&bytes).unwrap();
+        assert_eq!((parsed.cliff_time, parsed.deadline), (150, 999));
+    }
+
+    #[test]
+    fn stream_rejects_bad_times() {
+        let mut fields = stream_fields(None);
+        fields[1] = be(100);
+        assert_eq!(
+            error_of(parse_stream(&payload(MessageCode::Stream, &fields))),
+            code(MessengerError::InvalidTimeRange)
+        );
+
+        let bytes = payload(MessageCode::StreamWithCliff, &stream_fields(Some(250)));
+        assert_eq!(
+            error_of(parse_stream_with_cliff(&bytes)),
+            code(MessengerError::InvalidCliffTime)
+        );
+    }
+
+    #[test]
+    fn update_stream_layout() {
+        let fields = [
+            be(100),
+            be(200),
+            be(5_000),
+            chain(4),
+            vec![1; 32],
+            vec![2; 32],
+            vec![3; 32],
+            vec![4; 32],
+            be(999),
+        ];
+        let parsed = parse_update_stream(&payload(MessageCode::UpdateStream, &fields)).unwrap();
+        assert_eq!(
+            (parsed.start_time, parsed.end_time, parsed.amount),
+            (100, 200, 5_000)
+        );
+        assert_eq!(parsed.sender, vec![1; 32]);
+        assert_eq!(parsed.receiver, vec![2; 32]);
+        assert_eq!(parsed.token_mint, key(3));
+        assert_eq!(parsed.data_account, key(4));
+        assert_eq!(parsed.deadline, 999);
+    }
+
+    #[test]
+    fn stream_action_layout() {
+        let parsed = parse_pause(&payload(MessageCode::Pause, &action_fields(&[]))).unwrap();
+        assert_eq!(parsed.wallet, vec![1; 32]);
+        assert_eq!(parsed.token_mint, key(3));
+        assert_eq!(parsed.counterparty, vec![2; 32]);
+        assert_eq!(parsed.data_account, key(4));
+        assert_eq!(parsed.deadline, 999);
+        assert_eq!(
+            (parsed.amount, parsed.refund, parsed.by_receiver),
+            (0, 0, false)
+        );
+
+        let bytes = payload(MessageCode::WithdrawStream, &action_fields(&[700]));
+        let parsed = parse_withdraw_stream(&bytes).unwrap();
+        assert_eq!((parsed.amount, parsed.refund), (700, 0));
+
+        let bytes = payload(MessageCode::CancelStream, &action_fields(&[300, 700, 1]));
+        let parsed = parse_cancel_stream(&bytes).unwrap();
+        assert_eq!(
+            (parsed.amount, parsed.refund, parsed.by_receiver),
+            (700, 300, true)
+        );
+
+        // The trailing fields are optional.
+        let bytes = payload(MessageCode::CancelStream, &action_fields(&[]));
+        let parsed = parse_cancel_stream(&bytes).unwrap();
+        assert_eq!(
+            (parsed.amount, parsed.refund, parsed.by_receiver),
+            (0, 0, false)
+        );
+    }
+
+    #[test]
+    fn transfer_layout() {
+        let fields = [
+            be(5_000),
+            chain(4),
+            vec![1; 32],
+            vec![3; 32],
+            vec![2; 32],
+            be(999),
+        ];
+        let bytes = payload(MessageCode::InstantTransfer, &fields);
+        let parsed = parse_instant_transfer(&bytes).unwrap();
+        assert_eq!(parsed.amount, 5_000);
+        assert_eq!(parsed.sender, vec![1; 32]);
+        assert_eq!(parsed.token_mint, key(3));
+        assert_eq!(parsed.receiver, vec![2; 32]);
+        assert_eq!((parsed.deadline, parsed.decimals), (999, None));
+
+        let mut bytes = payload(MessageCode::DirectTransfer, &fields);
+        assert_eq!(parse_direct_transfer(&bytes).unwrap().decimals, None);
+        bytes.push(6);
+        assert_eq!(parse_direct_transfer(&bytes).unwrap().decimals, Some(6));
+    }
+
+    #[test]
+    fn relay_layout() {
+        let instruction = RelayInstruction {
+            program_id: key(5),
+            accounts: vec![],
+            data: vec![1, 2, 3],
+        };
+        let instruction_bytes = instruction.try_to_vec().unwrap();
+        let bytes = payload(
+            MessageCode::Relay,
+            &[vec![1; 32], instruction_bytes.clone()],
+        );
+        let parsed = parse_relay(&bytes).unwrap();
+        assert_eq!(parsed.sender, vec![1; 32]);
+        assert_eq!(parsed.instruction.program_id, key(5));
+        assert_eq!(parsed.instruction.data, vec![1, 2, 3]);
+        assert_eq!(parsed.instruction_bytes, instruction_bytes);
+
+        let bytes = payload(MessageCode::Relay, &[vec![1; 32], vec![0; 3]]);
+        assert_eq!(
+            error_of(parse_relay(&bytes)),
+            code(MessengerError::InvalidPayload)
+        );
+    }
+
+    #[test]
+    fn zero_amount_and_chain_range() {
+        let bytes = payload(
+            MessageCode::Deposit,
+            &[be(0), chain(4), vec![1; 32], vec![3; 32]],
+        );
+        assert_eq!(
+            error_of(parse_deposit(&bytes)),
+            code(MessengerError::ZeroAmount)
+        );
+
+        let bytes = payload(
+            MessageCode::Deposit,
+            &[be(1), chain(u16::MAX as u64 + 1), vec![1; 32], vec![3; 32]],
+        );
+        assert_eq!(
+            error_of(parse_deposit(&bytes)),
+            code(MessengerError::ValueOutOfRange)
+        );
+    }
+
+    #[test]
+    fn truncated_payloads() {
+        let parsers: [(MessageCode, Parser<()>); 11] = [
+            (MessageCode::Deposit, |b| parse_deposit(b).map(|_| ())),
+            (MessageCode::Withdraw, |b| parse_withdraw(b).map(|_| ())),
+            (MessageCode::Stream, |b| parse_stream(b).map(|_| ())),
+            (MessageCode::StreamWithCliff, |b| {
+                parse_stream_with_cliff(b).map(|_| ())
+            }),
+            (MessageCode::UpdateStream, |b| {
+                parse_update_stream(b).map(|_| ())
+            }),
+            (MessageCode::Pause, |b| parse_pause(b).map(|_| ())),
+            (MessageCode::WithdrawStream, |b| {
+                parse_withdraw_stream(b).map(|_| ())
+            }),
+            (MessageCode::CancelStream, |b| {
+                parse_cancel_stream(b).map(|_| ())
+            }),
+            (MessageCode::InstantTransfer, |b| {
+                parse_instant_transfer(b).map(|_| ())
+            }),
+            (MessageCode::DirectTransfer, |b| {
+                parse_direct_transfer(b).map(|_| ())
+            }),
+            (MessageCode::Relay, |b| parse_relay(b).map(|_| ())),
+        ];
+        for (opcode, parse) in parsers {
+            for len in [1, fixed_len(opcode) - 1] {
+                let mut bytes = vec![0u8; len];
+                bytes[0] = opcode as u8;
+                assert_eq!(
+                    error_of(parse(&bytes)),
+                    code(MessengerError::InvalidPayload)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn split_version_v1() {
+        let (version, body) = split_version(&[MessageCode::Deposit as u8, 1, 2]).unwrap();
+        assert_eq!(version, PayloadVersion::V1);
+        assert_eq!(body, &[MessageCode::Deposit as u8, 1, 2]);
+    }
+
+    #[test]
+    fn split_version_rejects() {
+        let cases: [(&[u8], MessengerError); 6] = [
+            (&[], MessengerError::InvalidPayload),
+            (&[PAYLOAD_VERSION_PREFIX], MessengerError::InvalidPayload),
+            // Version 1 is only ever implicit, a payload marking itself as 1 is malformed.
+            (
+                &[PAYLOAD_VERSION_PREFIX, 0x01, 32, 2],
+                MessengerError::UnsupportedPayloadVersion,
+            ),
+            (
+                &[PAYLOAD_VERSION_PREFIX, 0x03, 32, 2],
+                MessengerError::UnsupportedPayloadVersion,
+            ),
+            (
+                &[PAYLOAD_VERSION_PREFIX, 0x02],
+                MessengerError::InvalidPayload,
+            ),
+            (
+                &[PAYLOAD_VERSION_PREFIX, 0x02, 32],
+                MessengerError::InvalidPayload,
+            ),
+        ];
+        for (payload, expected) in cases {
+            assert_eq!(error_of(split_version(payload)), code(expected));
+        }
+    }
+
+    #[test]
+    fn sender_len_matches() {
+        let payload = [PAYLOAD_VERSION_PREFIX, 2, SENDER_LEN as u8, 1, 2, 3];
+        let (version, body) = split_version(&payload).unwrap();
+        assert_eq!(version, PayloadVersion::V2);
+        assert_eq!(body, &[1, 2, 3]);
+    }
+
+    #[test]
+    fn sender_len_mismatch() {
+        for declared in [0u8, 20, 31, 33, u8::MAX] {
+            let payload = [PAYLOAD_VERSION_PREFIX, 2, declared, 1];
+            assert_eq!(
+                error_of(split_version(&payload)),
+                code(MessengerError::SenderLengthMismatch)
+            );
+        }
+    }
+}
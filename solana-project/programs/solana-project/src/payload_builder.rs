@@ -0,0 +1,433 @@
+// Client side encoders for the payloads store_msg decodes, byte for byte what the
+// EVM Encoder produces and the process_* functions read. Numbers are big endian,
+// chain ids are written as U256 like on the EVM side.
+// Arguments follow the Encoder functions one for one.
+#![allow(clippy::too_many_arguments)]
+use anchor_lang::prelude::*;
+use primitive_types::U256;
+
+use crate::constants::MessageCode;
+pub use crate::state::{RelayInstruction, TransactionAccount};
+
+struct PayloadWriter(Vec<u8>);
+
+impl PayloadWriter {
+    fn new(code: MessageCode) -> Self {
+        PayloadWriter(vec![code as u8])
+    }
+
+    fn u64(mut self, value: u64) -> Self {
+        self.0.extend_from_slice(&value.to_be_bytes());
+        self
+    }
+
+    fn flag(self, value: bool) -> Self {
+        self.u64(value as u64)
+    }
+
+    fn chain(mut self, chain_id: u16) -> Self {
+        let mut bytes = [0u8; 32];
+        U256::from(chain_id).to_big_endian(&mut bytes);
+        self.0.extend_from_slice(&bytes);
+        self
+    }
+
+    fn bytes(mut self, bytes: &[u8]) -> Self {
+        self.0.extend_from_slice(bytes);
+        self
+    }
+
+    fn key(self, key: &Pubkey) -> Self {
+        self.bytes(key.as_ref())
+    }
+}
+
+pub fn encode_deposit(
+    amount: u64,
+    to_chain: u16,
+    sender: [u8; 32],
+    mint: Pubkey,
+    deadline: u64,
+) -> Vec<u8> {
+    PayloadWriter::new(MessageCode::Deposit)
+        .u64(amount)
+        .chain(to_chain)
+        .bytes(&sender)
+        .key(&mint)
+        .u64(deadline)
+        .0
+}
+
+pub fn encode_stream(
+    start: u64,
+    end: u64,
+    amount: u64,
+    to_chain: u16,
+    sender: [u8; 32],
+    receiver: [u8; 32],
+    can_update: bool,
+    can_cancel: bool,
+    mint: Pubkey,
+    deadline: u64,
+) -> Vec<u8> {
+    PayloadWriter::new(MessageCode::Stream)
+        .u64(start)
+        .u64(end)
+        .u64(amount)
+        .chain(to_chain)
+        .bytes(&sender)
+        .bytes(&receiver)
+        .flag(can_update)
+        .flag(can_cancel)
+        .key(&mint)
+        .u64(deadline)
+        .0
+}
+
+// Same as encode_stream with the cliff before the deadline.
+pub fn encode_stream_with_cliff(
+    start: u64,
+    end: u64,
+    amount: u64,
+    to_chain: u16,
+    sender: [u8; 32],
+    receiver: [u8; 32],
+    can_update: bool,
+    can_cancel: bool,
+    mint: Pubkey,
+    cliff: u64,
+    deadline: u64,
+) -> Vec<u8> {
+    PayloadWriter::new(MessageCode::StreamWithCliff)
+        .u64(start)
+        .u64(end)
+        .u64(amount)
+        .chain(to_chain)
+        .bytes(&sender)
+        .bytes(&receiver)
+        .flag(can_update)
+        .flag(can_cancel)
+        .key(&mint)
+        .u64(cliff)
+        .u64(deadline)
+        .0
+}
+
+pub fn encode_update_stream(
+    start: u64,
+    end: u64,
+    amount: u64,
+    to_chain: u16,
+    sender: [u8; 32],
+    receiver: [u8; 32],
+    mint: Pubkey,
+    data_account: Pubkey,
+    deadline: u64,
+) -> Vec<u8> {
+    PayloadWriter::new(MessageCode::UpdateStream)
+        .u64(start)
+        .u64(end)
+        .u64(amount)
+        .chain(to_chain)
+        .bytes(&sender)
+        .bytes(&receiver)
+        .key(&mint)
+        .key(&data_account)
+        .u64(deadline)
+        .0
+}
+
+pub fn encode_pause(
+    to_chain: u16,
+    sender: [u8; 32],
+    mint: Pubkey,
+    receiver: [u8; 32],
+    data_account: Pubkey,
+    deadline: u64,
+) -> Vec<u8> {
+    PayloadWriter::new(MessageCode::Pause)
+        .chain(to_chain)
+        .bytes(&sender)
+        .key(&mint)
+        .bytes(&receiver)
+        .key(&data_account)
+        .u64(deadline)
+        .0
+}
+
+// An amount of 0 withdraws everything vested.
+pub fn encode_withdraw_stream(
+    to_chain: u16,
+    withdrawer: [u8; 32],
+    mint: Pubkey,
+    depositor: [u8; 32],
+    data_account: Pubkey,
+    deadline: u64,
+    amount: u64,
+) -> Vec<u8> {
+    PayloadWriter::new(MessageCode::WithdrawStream)
+        .chain(to_chain)
+        .bytes(&withdrawer)
+        .key(&mint)
+        .bytes(&depositor)
+        .key(&data_account)
+        .u64(deadline)
+        .u64(amount)
+        .0
+}
+
+// A split of 0 and 0 leaves the settlement to the stream program. by_receiver marks
+// a cancel signed by the receiver instead of the sender.
+pub fn encode_cancel_stream(
+    to_chain: u16,
+    sender: [u8; 32],
+    mint: Pubkey,
+    receiver: [u8; 32],
+    data_account: Pubkey,
+    deadline: u64,
+    sender_refund: u64,
+    receiver_settlement: u64,
+    by_receiver: bool,
+) -> Vec<u8> {
+    PayloadWriter::new(MessageCode::CancelStream)
+        .chain(to_chain)
+        .bytes(&sender)
+        .key(&mint)
+        .bytes(&receiver)
+        .key(&data_account)
+        .u64(deadline)
+        .u64(sender_refund)
+        .u64(receiver_settlement)
+        .flag(by_receiver)
+        .0
+}
+
+pub fn encode_withdraw(
+    amount: u64,
+    to_chain: u16,
+    withdrawer: [u8; 32],
+    mint: Pubkey,
+    deadline: u64,
+) -> Vec<u8> {
+    PayloadWriter::new(MessageCode::Withdraw)
+        .u64(amount)
+        .chain(to_chain)
+        .bytes(&withdrawer)
+        .key(&mint)
+        .u64(deadline)
+        .0
+}
+
+pub fn encode_instant_transfer(
+    amount: u64,
+    to_chain: u16,
+    sender: [u8; 32],
+    mint: Pubkey,
+    receiver: [u8; 32],
+    deadline: u64,
+) -> Vec<u8> {
+    PayloadWriter::new(MessageCode::InstantTransfer)
+        .u64(amount)
+        .chain(to_chain)
+        .bytes(&sender)
+        .key(&mint)
+        .bytes(&receiver)
+        .u64(deadline)
+        .0
+}
+
+// decimals is only written when set, a missing byte and a 0 byte decode differently.
+pub fn encode_direct_transfer(
+    amount: u64,
+    to_chain: u16,
+    sender: [u8; 32],
+    mint: Pubkey,
+    receiver: [u8; 32],
+    deadline: u64,
+    decimals: Option<u8>,
+) -> Vec<u8> {
+    let writer = PayloadWriter::new(MessageCode::DirectTransfer)
+        .u64(amount)
+        .chain(to_chain)
+        .bytes(&sender)
+        .key(&mint)
+        .bytes(&receiver)
+        .u64(deadline);
+    match decimals {
+        Some(decimals) => writer.bytes(&[decimals]).0,
+        None => writer.0,
+    }
+}
+
+pub fn encode_relay(sender: [u8; 32], instruction: &RelayInstruction) -> Result<Vec<u8>> {
+    Ok(PayloadWriter::new(MessageCode::Relay)
+        .bytes(&sender)
+        .bytes(&instruction.try_to_vec()?)
+        .0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::payload::*;
+
+    const SENDER: [u8; 32] = [1; 32];
+    const RECEIVER: [u8; 32] = [2; 32];
+
+    fn mint() -> Pubkey {
+        Pubkey::new_from_array([3; 32])
+    }
+
+    fn data_account() -> Pubkey {
+        Pubkey::new_from_array([4; 32])
+    }
+
+    #[test]
+    fn deposit_and_withdraw() {
+        let parsed = parse_deposit(&encode_deposit(500, 4, SENDER, mint(), 99)).unwrap();
+        assert_eq!(parsed.amount, 500);
+        assert_eq!(parsed.wallet, SENDER.to_vec());
+        assert_eq!((parsed.token_mint, parsed.deadline), (mint(), 99));
+
+        let parsed = parse_withdraw(&encode_withdraw(500, 4, RECEIVER, mint(), 99)).unwrap();
+        assert_eq!(parsed.amount, 500);
+        assert_eq!(parsed.wallet, RECEIVER.to_vec());
+        assert_eq!((parsed.token_mint, parsed.deadline), (mint(), 99));
+    }
+
+    #[test]
+    fn stream() {
+        let bytes = encode_stream(10, 20, 500, 4, SENDER, RECEIVER, false, true, mint(), 99);
+        let parsed = parse_stream(&bytes).unwrap();
+        assert_eq!(
+            (parsed.start_time, parsed.end_time, parsed.amount),
+            (10, 20, 500)
+        );
+        assert_eq!(parsed.sender, SENDER.to_vec());
+        assert_eq!(parsed.receiver, RECEIVER.to_vec());
+        assert!(!parsed.can_update && parsed.can_cancel);
+        assert_eq!(parsed.token_mint, mint());
+        assert_eq!((parsed.cliff_time, parsed.deadline), (0, 99));
+    }
+
+    #[test]
+    fn stream_with_cliff() {
+        let bytes = encode_stream_with_cliff(
+            10,
+            20,
+            500,
+            4,
+            SENDER,
+            RECEIVER,
+            true,
+            false,
+            mint(),
+            15,
+            99,
+        );
+        let parsed = parse_stream_with_cliff(&bytes).unwrap();
+        assert_eq!(
+            (parsed.start_time, parsed.end_time, parsed.amount),
+            (10, 20, 500)
+        );
+        assert!(parsed.can_update && !parsed.can_cancel);
+        assert_eq!((parsed.cliff_time, parsed.deadline), (15, 99));
+    }
+
+    #[test]
+    fn update_stream() {
+        let bytes =
+            encode_update_stream(10, 20, 500, 4, SENDER, RECEIVER, mint(), data_account(), 99);
+        let parsed = parse_update_stream(&bytes).unwrap();
+        assert_eq!(
+            (parsed.start_time, parsed.end_time, parsed.amount),
+            (10, 20, 500)
+        );
+        assert_eq!(parsed.sender, SENDER.to_vec());
+        assert_eq!(parsed.receiver, RECEIVER.to_vec());
+        assert_eq!(parsed.token_mint, mint());
+        assert_eq!(parsed.data_account, data_account());
+        assert_eq!(parsed.deadline, 99);
+    }
+
+    #[test]
+    fn stream_actions() {
+        let bytes = encode_pause(4, SENDER, mint(), RECEIVER, data_account(), 99);
+        let parsed = parse_pause(&bytes).unwrap();
+        assert_eq!(parsed.wallet, SENDER.to_vec());
+        assert_eq!(parsed.token_mint, mint());
+        assert_eq!(parsed.counterparty, RECEIVER.to_vec());
+        assert_eq!(parsed.data_account, data_account());
+        assert_eq!(parsed.deadline, 99);
+
+        let bytes = encode_withdraw_stream(4, RECEIVER, mint(), SENDER, data_account(), 99, 70);
+        let parsed = parse_withdraw_stream(&bytes).unwrap();
+        assert_eq!(parsed.wallet, RECEIVER.to_vec());
+        assert_eq!(parsed.counterparty, SENDER.to_vec());
+        assert_eq!((parsed.deadline, parsed.amount), (99, 70));
+
+        let bytes = encode_cancel_stream(
+            4,
+            SENDER,
+            mint(),
+            RECEIVER,
+            data_account(),
+            99,
+            30,
+            70,
+            true,
+        );
+        let parsed = parse_cancel_stream(&bytes).unwrap();
+        assert_eq!(parsed.wallet, SENDER.to_vec());
+        assert_eq!(parsed.counterparty, RECEIVER.to_vec());
+        assert_eq!(
+            (
+                parsed.deadline,
+                parsed.refund,
+                parsed.amount,
+                parsed.by_receiver
+            ),
+            (99, 30, 70, true)
+        );
+    }
+
+    #[test]
+    fn transfers() {
+        let bytes = encode_instant_transfer(500, 4, SENDER, mint(), RECEIVER, 99);
+        let parsed = parse_instant_transfer(&bytes).unwrap();
+        assert_eq!(parsed.amount, 500);
+        assert_eq!(parsed.sender, SENDER.to_vec());
+        assert_eq!(parsed.token_mint, mint());
+        assert_eq!(parsed.receiver, RECEIVER.to_vec());
+        assert_eq!((parsed.deadline, parsed.decimals), (99, None));
+
+        for decimals in [None, Some(0), Some(8)] {
+            let bytes = encode_direct_transfer(500, 4, SENDER, mint(), RECEIVER, 99, decimals);
+            let parsed = parse_direct_transfer(&bytes).unwrap();
+            assert_eq!(parsed.amount, 500);
+            assert_eq!(parsed.receiver, RECEIVER.to_vec());
+            assert_eq!((parsed.deadline, parsed.decimals), (99, decimals));
+        }
+    }
+
+    #[test]
+    fn relay() {
+        let instruction = RelayInstruction {
+            program_id: Pubkey::new_from_array([5; 32]),
+            accounts: vec![TransactionAccount {
+                pubkey: data_account(),
+                is_signer: false,
+                is_writable: true,
+            }],
+            data: vec![9, 8, 7],
+        };
+        let parsed = parse_relay(&encode_relay(SENDER, &instruction).unwrap()).unwrap();
+        assert_eq!(parsed.sender, SENDER.to_vec());
+        assert_eq!(parsed.instruction.program_id, instruction.program_id);
+        assert_eq!(parsed.instruction.accounts.len(), 1);
+        assert_eq!(parsed.instruction.accounts[0].pubkey, data_account());
+        assert!(parsed.instruction.accounts[0].is_writable);
+        assert_eq!(parsed.instruction.data, vec![9, 8, 7]);
+        assert_eq!(parsed.instruction_bytes, instruction.try_to_vec().unwrap());
+    }
+}
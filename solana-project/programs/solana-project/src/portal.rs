@@ -1,3 +1,6 @@
+// Token bridge payload layouts, only part of them is used here.
+#![allow(dead_code)]
+
 use anchor_lang::prelude::*;
 use anchor_lang::solana_program::program_error::ProgramError::InvalidAccountData;
 use primitive_types::U256;
@@ -6,11 +9,8 @@ use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 use std::{
     cmp,
     io::{Cursor, Read, Write},
-    str::FromStr,
 };
 
-use crate::constants::TOKEN_BRIDGE_ADDRESS;
-
 pub trait SerializePayload: Sized {
     fn serialize<W: Write>(&self, writer: &mut W) -> std::result::Result<(), Error>;
 
@@ -271,13 +271,13 @@ impl SerializePayload for PayloadAssetMeta {
 
         let mut symbol: [u8; 32] = [0; 32];
         let count = cmp::min(symbol.len(), self.symbol.len());
-        symbol[..count].copy_from_slice(self.symbol[..count].as_bytes());
+        symbol[..count].copy_from_slice(&self.symbol.as_bytes()[..count]);
 
         writer.write_all(&symbol)?;
 
         let mut name: [u8; 32] = [0; 32];
         let count = cmp::min(name.len(), self.name.len());
-        name[..count].copy_from_slice(self.name[..count].as_bytes());
+        name[..count].copy_from_slice(&self.name.as_bytes()[..count]);
 
         writer.write_all(&name)?;
 
@@ -291,6 +291,14 @@ pub enum PortalError {
     CustomZeroError,
 }
 
+// Token bridge account describing the original asset of a wrapped mint.
+#[derive(AnchorDeserialize, AnchorSerialize, Default)]
+pub struct WrappedMeta {
+    pub chain: u16,
+    pub token_address: [u8; 32],
+    pub original_decimals: u8,
+}
+
 #[derive(AnchorDeserialize, AnchorSerialize, Default)]
 pub struct TransferWrappedData {
     pub nonce: u32,
@@ -308,12 +316,3 @@ pub struct TransferNativeData {
     pub target_address: [u8; 32],
     pub target_chain: u16,
 }
-
-#[derive(Clone)]
-pub struct TokenPortalBridge;
-
-impl anchor_lang::Id for TokenPortalBridge {
-    fn id() -> Pubkey {
-        Pubkey::from_str(TOKEN_BRIDGE_ADDRESS).unwrap()
-    }
-}
@@ -7,6 +7,104 @@ use borsh::{BorshDeserialize, BorshSerialize};
 pub struct Config {
     pub owner: Pubkey,
     pub nonce: u32,
+    // Wormhole programs of the cluster this deployment talks to.
+    pub core_bridge: Pubkey,
+    pub token_bridge: Pubkey,
+    // Transactions built but not yet executed, across all senders.
+    pub open_transactions: u64,
+    // Upper bound on open_transactions, 0 means unlimited.
+    pub max_open_transactions: u64,
+    // Lowest VAA consistency level accepted by store_msg.
+    pub min_consistency_level: u8,
+    // Warn when a decoded amount exceeds the supply of its mint.
+    pub check_amount_plausibility: bool,
+    // Bridge whatever is available instead of failing when a transfer exceeds the balance.
+    pub cap_approval_to_balance: bool,
+    // Reject direct and instant transfers whose receiver is the sender.
+    pub reject_self_transfer: bool,
+    // Require the program of a built transaction to match the one stored for the operation.
+    pub check_program_id: bool,
+    // Direct transfers are only staged and need execute_direct_transfer_* to bridge.
+    pub two_phase_direct_transfer: bool,
+    // Re-check at execution that the built instruction still carries the stored amount.
+    pub enforce_amount_binding: bool,
+    // Emit UnsupportedCode for payloads with an unknown opcode before rejecting them.
+    pub log_rejected_codes: bool,
+    // Round amounts down when rescaling from source_decimals instead of rejecting them.
+    pub allow_precision_loss: bool,
+    // Set by the owner while the Wormhole bridge is paused by governance, the bridge
+    // accounts don't expose this so it can't be read from them.
+    pub bridge_paused: bool,
+    // Check pda_signer against the chain stored for the operation before signing with it.
+    pub check_pda_signer: bool,
+    // Estimated compute units store_msg_batch may spend before stopping early, 0 for no limit.
+    pub batch_compute_budget: u64,
+    // Outbound rate limit, see RateLimit. A max of 0 disables it.
+    pub max_amount_per_window: u64,
+    pub window_seconds: u64,
+    // Mints payloads may reference, empty accepts any mint.
+    pub allowed_mints: Vec<Pubkey>,
+    // Wormhole chain ids transfers may target, empty accepts any chain but Solana.
+    pub target_chains: Vec<u16>,
+    // Chains with an EmitterAddrAccount, kept by register_chain and deregister_chain.
+    pub registered_chains: Vec<u16>,
+    // Operational key allowed to push bridge transfers next to the owner, which keeps
+    // governance. The default key means no relayer is set.
+    pub relayer_authority: Pubkey,
+    // The token bridge emitter and its core bridge sequence, pinned by
+    // set_portal_accounts. Transfers must use these once they are set.
+    pub portal_emitter: Pubkey,
+    pub portal_sequence: Pubkey,
+    // Transfers below this many base units aren't worth the bridge fee, 0 for no minimum.
+    pub min_transfer_amount: u64,
+    // Last day messages were stored on, its DailyStats is rolled up once a later day starts.
+    pub last_stats_day: u64,
+}
+
+// Argument of update_risk_params, only the fields that are set get written.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct RiskParams {
+    pub min_consistency_level: Option<u8>,
+    pub max_open_transactions: Option<u64>,
+    pub batch_compute_budget: Option<u64>,
+    pub max_amount_per_window: Option<u64>,
+    pub window_seconds: Option<u64>,
+    pub min_transfer_amount: Option<u64>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq)]
+pub enum ConfigFlag {
+    CheckAmountPlausibility,
+    CapApprovalToBalance,
+    RejectSelfTransfer,
+    CheckProgramId,
+    TwoPhaseDirectTransfer,
+    EnforceAmountBinding,
+    LogRejectedCodes,
+    AllowPrecisionLoss,
+    BridgePaused,
+    CheckPdaSigner,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq)]
+pub enum OperationStage {
+    Stored,
+    Built,
+    Executed,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq)]
+pub enum OperationStatus {
+    Succeeded,
+    Failed,
+    Aborted,
+}
+
+// Borsh layout of StateExport.data, emitters are keyed by their account address.
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct ExportedState {
+    pub config: Config,
+    pub emitters: Vec<(Pubkey, EmitterAddrAccount)>,
 }
 
 #[account]
@@ -14,6 +112,12 @@ pub struct Config {
 pub struct EmitterAddrAccount {
     pub chain_id: u16,
     pub emitter_addr: String,
+    // Decimals amounts from this chain are encoded with, 0 when already in mint units.
+    pub source_decimals: u8,
+    // emitter_addr decoded at registration, compared against VAAs and used in seeds.
+    pub emitter_bytes: [u8; 32],
+    // The chain's own id, e.g. 137 for Polygon, for display and routing. 0 when unset.
+    pub native_chain_id: u64,
 }
 
 //Empty account, we just need to check that it *exists*
@@ -22,7 +126,7 @@ pub struct ProcessedVAA {}
 
 #[account]
 pub struct Transaction {
-    //450
+    //482
     // Target program to execute against.32
     pub program_id: Pubkey,
     // Accounts requried for the transaction.8+9*34
@@ -31,9 +135,14 @@ pub struct Transaction {
     pub data: Vec<u8>,
     // Boolean ensuring one time execution.1+8
     pub did_execute: bool,
+    // Who paid the rent of this account, it goes back to them on close.32
+    pub payer: Pubkey,
+    // Operation the transaction was built from, the only one it executes against.32
+    pub data_storage: Pubkey,
 }
 
 #[account]
+#[derive(Default)]
 // TODO: can_update and cancel are bools
 pub struct TransactionData {
     pub sender: Vec<u8>,
@@ -41,11 +150,37 @@ pub struct TransactionData {
     pub data_account: Pubkey,
     pub from_chain_id: u64,
     pub token_mint: Pubkey,
+    // Amounts are always in base units (the smallest denomination) of token_mint,
+    // exactly as they will be passed to the token program.
     pub amount: u64,
     pub start_time: u64,
     pub end_time: u64,
     pub can_update: bool,
     pub can_cancel: bool,
+    // 0 when the stream has no cliff.
+    pub cliff_time: u64,
+    // Unix timestamp after which the operation can no longer be created or executed, 0 for none.
+    pub deadline: u64,
+    // Program the transaction built from this operation has to target.
+    pub program_id: Pubkey,
+    // Opcode of the message this was stored from.
+    pub msg_type: u8,
+    // Decimals the source chain assumed for token_mint, when the payload says so.
+    pub decimals: Option<u8>,
+    // Layout version, see DATA_STORAGE_VERSION. New fields are only ever appended
+    // so older accounts can be migrated by growing them.
+    pub version: u8,
+    // Cancel only: expected refund to the sender, amount holds the receiver's share.
+    pub refund_amount: u64,
+    // Relay only: keccak256 of the borsh encoded RelayInstruction.
+    pub instruction_hash: [u8; 32],
+    // Cancel only: the receiver asked for the cancel instead of the sender.
+    pub cancel_by_receiver: bool,
+    // native_chain_id of the emitter chain when the message was stored.
+    pub native_chain_id: u64,
+    // Direct transfer only: target chain and fee recorded when it was staged.
+    pub staged_target_chain: u16,
+    pub staged_fee: u64,
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize, Clone)]
@@ -57,13 +192,69 @@ pub struct TransactionAccount {
 
 #[account]
 pub struct TransactionStatus{
-    pub executed: bool
+    pub executed: bool,
+    // Direct transfer waiting for its second phase.
+    pub staged: bool,
+    // A transaction has been built for the operation, executed is only set once it ran.
+    pub created: bool,
 } 
 
 #[account]
 #[derive(Default)]
 pub struct Count {
-    pub count: u8,
+    pub count: u64,
+}
+
+// Amount bridged out in the current window, summed in base units across mints.
+#[account]
+#[derive(Default)]
+pub struct RateLimit {
+    pub window_start: i64,
+    pub amount: u64,
+}
+
+// Totals of the messages stored on one day, one account per day seeded by
+// [b"daily_stats", day.to_le_bytes()] with day counted since the unix epoch.
+// Volumes are kept per mint, base units of different mints don't add up.
+// The first message of a later day emits the totals as a DailyRollup.
+#[account]
+#[derive(Default)]
+pub struct DailyStats {
+    // Days since the unix epoch.
+    pub day: u64,
+    pub count: u64,
+    // The first MAX_DAILY_STATS_MINTS mints seen that day, later ones only add to count.
+    pub mints: Vec<MintVolume>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct MintVolume {
+    pub mint: Pubkey,
+    pub count: u64,
+    // Sum of the amounts of the stored messages, in base units of mint.
+    pub volume: u64,
+}
+
+// One entry of register_chains, the arguments register_chain takes.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct ChainRegistration {
+    pub chain_id: u16,
+    pub emitter_addr: String,
+    pub native_chain_id: u64,
+}
+
+// Instruction carried by a Relay payload, borsh encoded after the sender.
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct RelayInstruction {
+    pub program_id: Pubkey,
+    pub accounts: Vec<TransactionAccount>,
+    pub data: Vec<u8>,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct CancelSplit {
+    pub sender_refund: u64,
+    pub receiver_settlement: u64,
 }
 
 #[derive(BorshDeserialize, BorshSerialize)]
@@ -80,6 +271,16 @@ pub struct Stream {
     pub can_update: bool,
 }
 
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct StreamWithCliff {
+    pub start_time: u64,
+    pub end_time: u64,
+    pub amount: u64,
+    pub can_cancel: bool,
+    pub can_update: bool,
+    pub cliff_time: u64,
+}
+
 #[derive(BorshDeserialize, BorshSerialize)]
 pub struct StreamUpdate {
     pub start_time: u64,
@@ -1,8 +1,9 @@
+// Core bridge instruction layouts, only part of them is used here.
+#![allow(dead_code)]
+
 use anchor_lang::prelude::*;
 use borsh::{BorshDeserialize, BorshSerialize};
-use std::{io::Write, str::FromStr};
-
-use crate::constants::CORE_BRIDGE_ADDRESS;
+use std::io::Write;
 
 #[derive(AnchorDeserialize, AnchorSerialize)]
 pub struct PostMessageData {
@@ -57,6 +58,12 @@ pub struct BridgeConfig {
     pub fee: u64,
 }
 
+#[derive(AnchorDeserialize, AnchorSerialize, Clone)]
+pub struct SequenceTracker {
+    /// Sequence the next message of the emitter will get
+    pub sequence: u64,
+}
+
 #[derive(Debug)]
 #[repr(transparent)]
 pub struct PostedMessageData(pub MessageData);
@@ -109,12 +116,3 @@ impl AnchorDeserialize for PostedMessageData {
         ))
     }
 }
-
-#[derive(Clone)]
-pub struct WormholeCoreBridge;
-
-impl anchor_lang::Id for WormholeCoreBridge {
-    fn id() -> Pubkey {
-        Pubkey::from_str(CORE_BRIDGE_ADDRESS).unwrap()
-    }
-}
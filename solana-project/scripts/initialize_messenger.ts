@@ -12,6 +12,12 @@ async function main() {
   console.log(KEYPAIR.publicKey.toBase58());
   const CONN_STRING = 'https://api.devnet.solana.com';
   const CONTRACT_ADDRESS = 'F56A1FPDGsNUrqHNjmHZ36txyDTY8VYA7UEWV4SwxQAF';
+  const CORE_BRIDGE_ADDRESS = new anchor.web3.PublicKey(
+    '3u8hJUVTA4jH1wYAyUur7FFZVQ8H635K3tSHHF4ssjQ5'
+  );
+  const TOKEN_BRIDGE_ADDRESS = new anchor.web3.PublicKey(
+    'DZnkkTmCiFWfYTfT41X3Rd1kDgozqzxWaHqsw6W4x2oe'
+  );
   const IDL = JSON.parse(
     fs.readFileSync('target/idl/solana_project.json').toString()
   );
@@ -32,7 +38,7 @@ async function main() {
   );
 
   await program.methods
-    .initialize()
+    .initialize(CORE_BRIDGE_ADDRESS, TOKEN_BRIDGE_ADDRESS)
     .accounts({
       config: config_acc,
       owner: KEYPAIR.publicKey,